@@ -0,0 +1,195 @@
+use std::collections::BTreeMap;
+
+use chrono::Utc;
+use futures::StreamExt;
+use reqwest::{
+    header::CONTENT_TYPE,
+    multipart::{Form, Part},
+    Body, Client, Method, RequestBuilder,
+};
+use ring::{
+    digest::{digest, Context, SHA256},
+    hmac,
+};
+use serde_json::json;
+use tokio::fs::File;
+use tokio_util::io::ReaderStream;
+
+use crate::{
+    config,
+    errors::Error,
+    jwt::{hex, random_token},
+    models::{image::Image, media::Media},
+    upload::Upload,
+};
+
+const HASH_HEADER: &str = "x-cdn-hash";
+const TIMESTAMP_HEADER: &str = "x-cdn-timestamp";
+const NONCE_HEADER: &str = "x-cdn-nonce";
+const DIGEST_HEADER: &str = "x-cdn-digest";
+const SIGNATURE_HEADER: &str = "x-cdn-signature";
+
+fn url(path: &str) -> String {
+    format!("{}{}", config::get().cdn.internal_url, path)
+}
+
+fn sign(message: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, config::get().cdn.secret.as_bytes());
+
+    hex(hmac::sign(&key, message.as_bytes()).as_ref())
+}
+
+// the cdn accepts each nonce once, so every request is signed on its own.
+// digest is the sha256 of the body, or of the file for uploads
+fn signed(method: Method, path: &str, hash: &str, digest: &str) -> Result<RequestBuilder, Error> {
+    let timestamp = Utc::now().timestamp();
+    let nonce = random_token()?;
+    let signature = sign(&format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method, path, hash, timestamp, nonce, digest
+    ));
+
+    let request = Client::new()
+        .request(method, url(path))
+        .header(HASH_HEADER, hash)
+        .header(TIMESTAMP_HEADER, timestamp)
+        .header(NONCE_HEADER, nonce)
+        .header(DIGEST_HEADER, digest)
+        .header(SIGNATURE_HEADER, signature);
+
+    Ok(request)
+}
+
+// without a body
+fn request(method: Method, path: &str, hash: &str) -> Result<RequestBuilder, Error> {
+    signed(method, path, hash, &hex(digest(&SHA256, b"").as_ref()))
+}
+
+// the file is streamed from disk, the cdn answers with what it read from it
+pub async fn upload(image: &Image, upload: &Upload) -> Result<Media, Error> {
+    let file = File::open(&upload.file.path)
+        .await
+        .map_err(|_| Error::Upload)?;
+    let body = Body::wrap_stream(ReaderStream::new(file));
+
+    let part = Part::stream_with_length(body, upload.size)
+        .file_name(image.hash.clone())
+        .mime_str(&image.content_type)
+        .map_err(|_| Error::WrongType)?;
+
+    let multipart = Form::new().part("file", part);
+
+    signed(Method::POST, "/", &image.hash, &upload.sha256)?
+        .multipart(multipart)
+        .send()
+        .await
+        .map_err(|_| Error::Upload)?
+        .error_for_status()
+        .map_err(|_| Error::Upload)?
+        .json()
+        .await
+        .map_err(|_| Error::Upload)
+}
+
+// private files are only served with a signature valid until expires
+pub fn signed_url(url: String, hash: &str) -> String {
+    let expire = config::get().cdn.url_expire;
+
+    // rounded, so the url stays the same and cacheable for a while
+    let expires = (Utc::now().timestamp() / expire + 2) * expire;
+    let signature = sign(&format!("url\n{}\n{}", hash, expires));
+
+    format!("{}?expires={}&signature={}", url, expires, signature)
+}
+
+pub async fn visibility(hash: &str, private: bool) -> Result<(), Error> {
+    let body = json!({ "private": private }).to_string();
+    let sha256 = hex(digest(&SHA256, body.as_bytes()).as_ref());
+
+    signed(Method::PATCH, &format!("/{}", hash), hash, &sha256)?
+        .header(CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await
+        .map_err(|_| Error::Cdn)?
+        .error_for_status()
+        .map_err(|_| Error::Cdn)?;
+
+    Ok(())
+}
+
+// reads a stored original back, for images uploaded before sha256 was computed
+pub async fn sha256(hash: &str) -> Result<String, Error> {
+    // signed in case the image is private
+    let url = signed_url(url(&format!("/{}", hash)), hash);
+
+    let mut body = Client::new()
+        .get(url)
+        .send()
+        .await
+        .map_err(|_| Error::Cdn)?
+        .error_for_status()
+        .map_err(|_| Error::Cdn)?
+        .bytes_stream();
+
+    let mut context = Context::new(&SHA256);
+    while let Some(chunk) = body.next().await {
+        context.update(&chunk.map_err(|_| Error::Cdn)?);
+    }
+
+    Ok(hex(context.finish().as_ref()))
+}
+
+// for images uploaded before their metadata was kept
+pub async fn media(hash: &str) -> Result<Media, Error> {
+    let url = signed_url(url(&format!("/meta/{}", hash)), hash);
+
+    Client::new()
+        .get(url)
+        .send()
+        .await
+        .map_err(|_| Error::Cdn)?
+        .error_for_status()
+        .map_err(|_| Error::Cdn)?
+        .json()
+        .await
+        .map_err(|_| Error::Cdn)
+}
+
+// rebuilds the thumbnails with the presets the cdn has now
+pub async fn thumbnails(hash: &str) -> Result<(), Error> {
+    request(Method::POST, &format!("/thumb/{}", hash), hash)?
+        .send()
+        .await
+        .map_err(|_| Error::Cdn)?
+        .error_for_status()
+        .map_err(|_| Error::Cdn)?;
+
+    Ok(())
+}
+
+// removes the original and its thumbnails
+pub async fn delete(hash: &str) -> Result<(), Error> {
+    request(Method::DELETE, &format!("/{}", hash), hash)?
+        .send()
+        .await
+        .map_err(|_| Error::Cdn)?
+        .error_for_status()
+        .map_err(|_| Error::Cdn)?;
+
+    Ok(())
+}
+
+// every hash with a file on the cdn, originals and thumbnails,
+// with the unix time it was last written
+pub async fn stored() -> Result<BTreeMap<String, i64>, Error> {
+    request(Method::GET, "/", "")?
+        .send()
+        .await
+        .map_err(|_| Error::Cdn)?
+        .error_for_status()
+        .map_err(|_| Error::Cdn)?
+        .json()
+        .await
+        .map_err(|_| Error::Cdn)
+}
@@ -0,0 +1,247 @@
+use std::{env, fs, net::SocketAddr, path::PathBuf, str::FromStr, sync::OnceLock};
+
+use reqwest::Url;
+use serde::Deserialize;
+
+use crate::errors::Error;
+
+/* Configuration
+
+   Read from the toml file at $CONFIG (default: config.toml, optional),
+   then every field can be overridden by its environment variable.
+
+   bind                   BIND_ADDR
+   admins                 ADMINS              (user names made admin at startup and signup, comma separated)
+   [database]
+   url                    DATABASE_URL
+   username               DATABASE_USERNAME
+   password               DATABASE_PASSWORD
+   namespace              DATABASE_NAMESPACE
+   name                   DATABASE_NAME
+   [cdn]
+   public_url             CDN_PUBLIC_URL      (used in responses)
+   internal_url           CDN_INTERNAL_URL    (used for uploads, without a path, requests are signed with theirs)
+   secret                 CDN_SECRET          (same as the cdn api secret)
+   url_expire             CDN_URL_EXPIRE      (seconds a private image url is valid)
+   presets                CDN_PRESETS         (thumbnail sizes, comma separated)
+   gc_grace               CDN_GC_GRACE        (seconds a file is kept by the gc, uploads in progress have no record yet)
+   [upload]
+   max_size               UPLOAD_MAX_SIZE     (bytes)
+   temp_dir               UPLOAD_TEMP_DIR     (files are written there while uploading)
+   expire                 UPLOAD_EXPIRE       (seconds a resumable upload is kept)
+   concurrency            UPLOAD_CONCURRENCY  (files of a batch stored at the same time)
+   batch_files            UPLOAD_BATCH_FILES  (most files in a batch)
+   batch_size             UPLOAD_BATCH_SIZE   (bytes, all the files of a batch)
+*/
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub bind: SocketAddr,
+    pub admins: Vec<String>,
+    pub database: DatabaseConfig,
+    pub cdn: CdnConfig,
+    pub upload: UploadConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct DatabaseConfig {
+    pub url: String,
+    pub username: String,
+    pub password: String,
+    pub namespace: String,
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct CdnConfig {
+    pub public_url: String,
+    pub internal_url: String,
+    pub secret: String,
+    pub url_expire: i64,
+    pub presets: Vec<String>,
+    pub gc_grace: i64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct UploadConfig {
+    pub max_size: u64,
+    pub temp_dir: PathBuf,
+    pub expire: i64,
+    pub concurrency: usize,
+    pub batch_files: usize,
+    pub batch_size: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind: SocketAddr::from(([127, 0, 0, 1], 5000)),
+            admins: vec![],
+            database: DatabaseConfig::default(),
+            cdn: CdnConfig::default(),
+            upload: UploadConfig::default(),
+        }
+    }
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            username: "root".to_string(),
+            password: "root".to_string(),
+            namespace: "booru".to_string(),
+            name: "booru".to_string(),
+        }
+    }
+}
+
+impl Default for CdnConfig {
+    fn default() -> Self {
+        Self {
+            public_url: "http://localhost:4000".to_string(),
+            internal_url: "http://localhost:4000".to_string(),
+            secret: String::new(),
+            url_expire: 3600,
+            presets: vec!["small".to_string(), "medium".to_string(), "sample".to_string()],
+            gc_grace: 60 * 60,
+        }
+    }
+}
+
+impl Default for UploadConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 50 * 1024 * 1024,
+            temp_dir: env::temp_dir().join("booru"),
+            expire: 24 * 60 * 60,
+            concurrency: 4,
+            batch_files: 100,
+            batch_size: 500 * 1024 * 1024,
+        }
+    }
+}
+
+fn invalid(reason: &str) -> Error {
+    println!("Config: {}", reason);
+    Error::Config
+}
+
+fn set<T: FromStr>(value: &mut T, key: &str) -> Result<(), Error> {
+    if let Ok(v) = env::var(key) {
+        *value = v
+            .parse()
+            .map_err(|_| invalid(&format!("cannot parse {}", key)))?;
+    }
+
+    Ok(())
+}
+
+fn check_url(url: &mut String, name: &str) -> Result<(), Error> {
+    Url::parse(url).map_err(|_| invalid(&format!("{} is not a valid url", name)))?;
+
+    while url.ends_with('/') {
+        url.pop();
+    }
+
+    Ok(())
+}
+
+impl Config {
+    pub fn load() -> Result<Self, Error> {
+        let path = env::var("CONFIG").unwrap_or_else(|_| "config.toml".to_string());
+
+        let mut config = match fs::read_to_string(&path) {
+            Ok(data) => toml::from_str(&data).map_err(|e| invalid(&e.to_string()))?,
+            Err(_) => Config::default(),
+        };
+
+        set(&mut config.bind, "BIND_ADDR")?;
+        set(&mut config.database.url, "DATABASE_URL")?;
+        set(&mut config.database.username, "DATABASE_USERNAME")?;
+        set(&mut config.database.password, "DATABASE_PASSWORD")?;
+        set(&mut config.database.namespace, "DATABASE_NAMESPACE")?;
+        set(&mut config.database.name, "DATABASE_NAME")?;
+        set(&mut config.cdn.public_url, "CDN_PUBLIC_URL")?;
+        set(&mut config.cdn.internal_url, "CDN_INTERNAL_URL")?;
+        set(&mut config.cdn.secret, "CDN_SECRET")?;
+        set(&mut config.cdn.url_expire, "CDN_URL_EXPIRE")?;
+        set(&mut config.cdn.gc_grace, "CDN_GC_GRACE")?;
+        set(&mut config.upload.max_size, "UPLOAD_MAX_SIZE")?;
+        set(&mut config.upload.temp_dir, "UPLOAD_TEMP_DIR")?;
+        set(&mut config.upload.expire, "UPLOAD_EXPIRE")?;
+        set(&mut config.upload.concurrency, "UPLOAD_CONCURRENCY")?;
+        set(&mut config.upload.batch_files, "UPLOAD_BATCH_FILES")?;
+        set(&mut config.upload.batch_size, "UPLOAD_BATCH_SIZE")?;
+
+        if let Ok(admins) = env::var("ADMINS") {
+            config.admins = admins.split(',').map(|a| a.trim().to_string()).collect();
+        }
+        if let Ok(presets) = env::var("CDN_PRESETS") {
+            config.cdn.presets = presets.split(',').map(|p| p.trim().to_string()).collect();
+        }
+
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn validate(&mut self) -> Result<(), Error> {
+        let db = &self.database;
+        if db.url.is_empty() {
+            return Err(invalid("database url is missing"));
+        }
+        if db.username.is_empty() || db.password.is_empty() {
+            return Err(invalid("database credentials are missing"));
+        }
+        if db.namespace.is_empty() || db.name.is_empty() {
+            return Err(invalid("database namespace and name are required"));
+        }
+
+        check_url(&mut self.cdn.public_url, "cdn public_url")?;
+        check_url(&mut self.cdn.internal_url, "cdn internal_url")?;
+        if self.cdn.secret.is_empty() {
+            return Err(invalid("cdn secret is missing"));
+        }
+        if self.cdn.url_expire <= 0 {
+            return Err(invalid("cdn url_expire must be positive"));
+        }
+        if self.cdn.gc_grace < 0 {
+            return Err(invalid("cdn gc_grace cannot be negative"));
+        }
+
+        let upload = &self.upload;
+        if upload.max_size == 0 {
+            return Err(invalid("upload max_size must be positive"));
+        }
+        if upload.expire <= 0 {
+            return Err(invalid("upload expire must be positive"));
+        }
+        if upload.concurrency == 0 {
+            return Err(invalid("upload concurrency must be positive"));
+        }
+        if upload.batch_files == 0 || upload.batch_size == 0 {
+            return Err(invalid("upload batch_files and batch_size must be positive"));
+        }
+        fs::create_dir_all(&upload.temp_dir)
+            .map_err(|_| invalid(&format!("cannot create {}", upload.temp_dir.display())))?;
+
+        Ok(())
+    }
+}
+
+pub fn init() -> Result<&'static Config, Error> {
+    let config = Config::load()?;
+
+    Ok(CONFIG.get_or_init(|| config))
+}
+
+pub fn get() -> &'static Config {
+    CONFIG.get().expect("Config not initialized")
+}
@@ -7,9 +7,17 @@ use surrealdb::{
 
 use crate::errors::Error;
 
-use self::{image::ImageDB, tag::TagDB, user::UserDB};
+use self::{
+    alias::AliasDB, apikey::ApiKeyDB, image::ImageDB, refresh::RefreshDB,
+    resumable::ResumableDB, tag::TagDB, user::UserDB,
+};
 
+pub mod alias;
+pub mod apikey;
+pub mod builder;
 pub mod image;
+pub mod refresh;
+pub mod resumable;
 pub mod tag;
 pub mod user;
 
@@ -48,6 +56,20 @@ impl Database {
         Ok(self)
     }
 
+    pub fn alias(&self) -> AliasDB {
+        AliasDB {
+            client: &self.client,
+            db: &self,
+        }
+    }
+
+    pub fn apikey(&self) -> ApiKeyDB {
+        ApiKeyDB {
+            client: &self.client,
+            db: &self,
+        }
+    }
+
     pub fn image(&self) -> ImageDB {
         ImageDB {
             client: &self.client,
@@ -55,6 +77,20 @@ impl Database {
         }
     }
 
+    pub fn refresh(&self) -> RefreshDB {
+        RefreshDB {
+            client: &self.client,
+            db: &self,
+        }
+    }
+
+    pub fn resumable(&self) -> ResumableDB {
+        ResumableDB {
+            client: &self.client,
+            db: &self,
+        }
+    }
+
     pub fn tag(&self) -> TagDB {
         TagDB {
             client: &self.client,
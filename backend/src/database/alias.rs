@@ -0,0 +1,63 @@
+use surrealdb::{engine::remote::ws::Client, Surreal};
+
+use crate::{
+    errors::Error,
+    models::{alias::Alias, tag::Tag},
+};
+
+use super::{builder::record, Database};
+
+pub struct AliasDB<'a> {
+    pub client: &'a Surreal<Client>,
+    pub db: &'a Database,
+}
+
+impl<'a> AliasDB<'a> {
+    pub async fn create(&self, name: &String, category: &String, tag: &Tag) -> Result<Alias, Error> {
+        let tag_id = record(tag.id.as_ref().ok_or(Error::InvalidId)?)?;
+
+        let mut res = self
+            .client
+            .query("create alias set name = $name, category = $category, tag = $tag")
+            .bind(("name", name))
+            .bind(("category", category))
+            .bind(("tag", tag_id))
+            .await?;
+
+        let alias: Option<Alias> = res.take(0)?;
+        alias.ok_or(Error::DatabaseError)
+    }
+
+    pub async fn get(&self, name: &String, category: &String) -> Result<Option<Alias>, Error> {
+        let mut res = self
+            .client
+            .query("select * from alias where name = $name and category = $category")
+            .bind(("name", name))
+            .bind(("category", category))
+            .await?;
+
+        Ok(res.take(0)?)
+    }
+
+    pub async fn delete(&self, alias: Alias) -> Result<(), Error> {
+        let id = alias.id.ok_or(Error::AliasNotFound)?;
+
+        self.client
+            .query("delete $alias")
+            .bind(("alias", record(&id)?))
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_tag(&self, tag: &Tag) -> Result<(), Error> {
+        let tag_id = record(tag.id.as_ref().ok_or(Error::InvalidId)?)?;
+
+        self.client
+            .query("delete alias where tag = $tag")
+            .bind(("tag", tag_id))
+            .await?;
+
+        Ok(())
+    }
+}
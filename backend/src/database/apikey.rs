@@ -0,0 +1,104 @@
+use std::collections::BTreeSet;
+
+use chrono::Utc;
+use surrealdb::{engine::remote::ws::Client, sql::Datetime, Surreal};
+
+use crate::{
+    errors::Error,
+    jwt::{hash_token, random_token},
+    models::{
+        apikey::{ApiKey, Scope},
+        user::User,
+    },
+};
+
+use super::{builder::record, Database};
+
+const PREFIX_SIZE: usize = 8;
+
+pub struct ApiKeyDB<'a> {
+    pub client: &'a Surreal<Client>,
+    pub db: &'a Database,
+}
+
+impl<'a> ApiKeyDB<'a> {
+    pub async fn create(&self, user: &User, name: &String, scopes: BTreeSet<Scope>) -> Result<(ApiKey, String), Error> {
+        let user_id = record(user.id.as_ref().ok_or(Error::InvalidId)?)?;
+
+        let key = random_token()?;
+        let prefix = key[..PREFIX_SIZE].to_string();
+
+        let mut res = self
+            .client
+            .query("create apikey set user = $user, name = $name, prefix = $prefix, hash = $hash, scopes = $scopes, created_at = $created_at")
+            .bind(("user", user_id))
+            .bind(("name", name))
+            .bind(("prefix", prefix))
+            .bind(("hash", hash_token(&key)))
+            .bind(("scopes", scopes))
+            .bind(("created_at", Datetime::from(Utc::now())))
+            .await?;
+
+        let apikey: Option<ApiKey> = res.take(0)?;
+        let apikey = apikey.ok_or(Error::DatabaseError)?;
+
+        Ok((apikey, key))
+    }
+
+    pub async fn get(&self, key: &str) -> Result<Option<ApiKey>, Error> {
+        let mut res = self
+            .client
+            .query("select * from apikey where hash = $hash")
+            .bind(("hash", hash_token(key)))
+            .await?;
+
+        Ok(res.take(0)?)
+    }
+
+    pub async fn from_name(&self, user: &User, name: &String) -> Result<Option<ApiKey>, Error> {
+        let user_id = record(user.id.as_ref().ok_or(Error::InvalidId)?)?;
+
+        let mut res = self
+            .client
+            .query("select * from apikey where user = $user and name = $name")
+            .bind(("user", user_id))
+            .bind(("name", name))
+            .await?;
+
+        Ok(res.take(0)?)
+    }
+
+    pub async fn from_user(&self, user: &User) -> Result<Vec<ApiKey>, Error> {
+        let user_id = record(user.id.as_ref().ok_or(Error::InvalidId)?)?;
+
+        let mut res = self
+            .client
+            .query("select * from apikey where user = $user order by created_at")
+            .bind(("user", user_id))
+            .await?;
+
+        Ok(res.take(0)?)
+    }
+
+    pub async fn used(&self, apikey: &ApiKey) -> Result<(), Error> {
+        let id = record(apikey.id.as_ref().ok_or(Error::InvalidId)?)?;
+
+        self.client
+            .query("update $apikey set last_used = time::now()")
+            .bind(("apikey", id))
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete(&self, apikey: ApiKey) -> Result<(), Error> {
+        let id = apikey.id.ok_or(Error::ApiKeyNotFound)?;
+
+        self.client
+            .query("delete $apikey")
+            .bind(("apikey", record(&id)?))
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,41 @@
+use std::collections::BTreeMap;
+
+use surrealdb::{
+    engine::remote::ws::Client,
+    method::Query,
+    sql::{thing, Thing, Value},
+    Surreal,
+};
+
+use crate::errors::Error;
+
+/// Collects the values of a dynamically built query so they are sent
+/// as `$params` instead of being formatted into the SurrealQL string.
+#[derive(Default)]
+pub struct QueryBuilder {
+    params: BTreeMap<String, Value>,
+}
+
+impl QueryBuilder {
+    /// Binds a value and returns the placeholder to insert in the query.
+    pub fn bind(&mut self, value: impl Into<Value>) -> String {
+        let name = format!("p{}", self.params.len());
+        self.params.insert(name.clone(), value.into());
+
+        format!("${}", name)
+    }
+
+    pub fn record(&mut self, id: &str) -> Result<String, Error> {
+        Ok(self.bind(record(id)?))
+    }
+
+    pub fn build<'a>(self, client: &'a Surreal<Client>, query: String) -> Query<'a, Client> {
+        self.params
+            .into_iter()
+            .fold(client.query(query), |q, param| q.bind(param))
+    }
+}
+
+pub fn record(id: &str) -> Result<Thing, Error> {
+    thing(id).map_err(|_| Error::InvalidId)
+}
@@ -1,13 +1,32 @@
 use futures::future::try_join_all;
-use surrealdb::{engine::remote::ws::Client, Surreal};
+use serde::Deserialize;
+use surrealdb::{
+    engine::remote::ws::Client,
+    sql::{
+        statements::{BeginStatement, CommitStatement},
+        Datetime,
+    },
+    Surreal,
+};
 
 use crate::{
     errors::Error,
-    models::{image::Image, tag::Tag, user::User},
+    filter::Term,
+    models::{
+        image::{Image, Rating},
+        media::Media,
+        tag::Tag,
+        user::User,
+    },
     pattern::Pattern,
 };
 
-use super::{Database, Session};
+use super::{
+    builder::{record, QueryBuilder},
+    Database, Session,
+};
+
+const SHA256_LENGTH: usize = 64;
 
 pub struct ImageDB<'a> {
     pub client: &'a Surreal<Client>,
@@ -15,20 +34,93 @@ pub struct ImageDB<'a> {
 }
 
 impl<'a> ImageDB<'a> {
-    pub async fn create(&self, image: &Image) -> Result<Image, Error> {
-        let image: Image = self
+    // the image, its uploader and its tags are created in one transaction
+    pub async fn create(&self, image: &Image, user: &User, tags: &[Tag]) -> Result<Image, Error> {
+        let user_id = record(user.id.as_ref().ok_or(Error::InvalidId)?)?;
+
+        let image = Image {
+            id: Some(format!("image:⟨{}⟩", image.hash)),
+            ..image.clone()
+        };
+        let image_id = record(image.id.as_ref().ok_or(Error::InvalidId)?)?;
+
+        let mut session = self
             .client
-            .create(("image", image.hash.clone()))
-            .content(image)
-            .await?;
+            .query(BeginStatement)
+            .query("create $image_id content $image;")
+            .query("relate $user_id->upload->$image_id;")
+            .bind(("image", &image))
+            .bind(("image_id", image_id))
+            .bind(("user_id", user_id));
+
+        for tag in tags {
+            session = self.tag(&image, tag, session)?;
+            session = self.db.tag().update(tag, 1, session)?;
+        }
 
-        Ok(image)
+        let response = session.query(CommitStatement).await?;
+        response.check()?;
+
+        self.get(&image.hash).await?.ok_or(Error::DatabaseError)
     }
 
+    // md5 is the record id, sha256 is looked up by field
     pub async fn get(&self, hash: &String) -> Result<Option<Image>, Error> {
+        if hash.len() == SHA256_LENGTH {
+            let mut res = self
+                .client
+                .query("select * from image where sha256 = $hash limit 1")
+                .bind(("hash", hash))
+                .await?;
+
+            return Ok(res.take(0)?);
+        }
+
         Ok(self.client.select(("image", hash.to_owned())).await?)
     }
 
+    pub async fn missing_sha256(&self) -> Result<Vec<Image>, Error> {
+        let mut res = self
+            .client
+            .query("select * from image where sha256 = none")
+            .await?;
+
+        Ok(res.take(0)?)
+    }
+
+    pub async fn set_sha256(&self, image: &Image, sha256: String) -> Result<(), Error> {
+        let id = record(image.id.as_ref().ok_or(Error::InvalidId)?)?;
+
+        self.client
+            .query("update $image set sha256 = $sha256")
+            .bind(("image", id))
+            .bind(("sha256", sha256))
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn missing_media(&self) -> Result<Vec<Image>, Error> {
+        let mut res = self
+            .client
+            .query("select * from image where media = none")
+            .await?;
+
+        Ok(res.take(0)?)
+    }
+
+    pub async fn set_media(&self, image: &Image, media: Media) -> Result<(), Error> {
+        let id = record(image.id.as_ref().ok_or(Error::InvalidId)?)?;
+
+        self.client
+            .query("update $image set media = $media")
+            .bind(("image", id))
+            .bind(("media", media))
+            .await?;
+
+        Ok(())
+    }
+
     pub async fn delete(&self, image: Image) -> Result<(), Error> {
         let id = image.id.ok_or(Error::ImageNotFound)?;
         let (_, id) = id.split_at(6);
@@ -37,22 +129,75 @@ impl<'a> ImageDB<'a> {
         Ok(())
     }
 
+    pub async fn set_private(&self, image: Image, private: bool) -> Result<Image, Error> {
+        let id = record(image.id.as_ref().ok_or(Error::InvalidId)?)?;
+
+        self.client
+            .query("update $image set private = $private")
+            .bind(("image", id))
+            .bind(("private", private))
+            .await?;
+
+        Ok(Image { private, ..image })
+    }
+
+    pub async fn set_rating(&self, image: Image, rating: Rating) -> Result<Image, Error> {
+        let id = record(image.id.as_ref().ok_or(Error::InvalidId)?)?;
+
+        self.client
+            .query("update $image set rating = $rating")
+            .bind(("image", id))
+            .bind(("rating", rating))
+            .await?;
+
+        Ok(Image { rating, ..image })
+    }
+
+    pub async fn hashes(&self) -> Result<Vec<String>, Error> {
+        let mut res = self.client.query("select value hash from image").await?;
+
+        Ok(res.take(0)?)
+    }
+
+    // images matching the pattern, without paging
+    pub async fn count(&self, pattern: Pattern<Term<Tag>>) -> Result<usize, Error> {
+        let mut builder = QueryBuilder::default();
+
+        let query = format!(
+            "select count() from (select *, ->tagged->tag.*.id as tag from image) where {} group all",
+            pattern.serialize("tag", &mut builder)?
+        );
+
+        #[derive(Deserialize)]
+        struct Count {
+            count: usize,
+        }
+
+        let mut res = builder.build(self.client, query).await?;
+        let count: Option<Count> = res.take(0)?;
+
+        Ok(count.map_or(0, |c| c.count))
+    }
+
     pub async fn search(
         &self,
-        pattern: Option<Pattern<Tag>>,
+        pattern: Option<Pattern<Term<Tag>>>,
         previous: Option<Image>,
     ) -> Result<Vec<Image>, Error> {
         let limit = 20;
         let mut query =
             String::from("select * from (select *, ->tagged->tag.*.id as tag from image)");
 
+        let mut builder = QueryBuilder::default();
+
         let mut wheres = vec![];
         if let Some(p) = pattern {
-            wheres.push(p.serialize("tag"));
+            wheres.push(p.serialize("tag", &mut builder)?);
         }
 
         if let Some(p) = previous {
-            wheres.push(format!("created_at < \"{}\"", p.created_at));
+            let created_at = builder.bind(Datetime::from(p.created_at));
+            wheres.push(format!("created_at < {}", created_at));
         }
 
         let clause = wheres
@@ -66,7 +211,7 @@ impl<'a> ImageDB<'a> {
 
         query = format!("{} order by created_at desc limit {}", query, limit);
 
-        let mut res = self.client.query(query).await?;
+        let mut res = builder.build(self.client, query).await?;
         let images: Vec<Image> = res.take(0)?;
 
         try_join_all(images.into_iter().map(|image| self.tagged(image))).await
@@ -79,16 +224,6 @@ impl<'a> ImageDB<'a> {
         Ok(Image::tagged(image, tags, user))
     }
 
-    pub async fn user(&self, image: &Image, user: &User) -> Result<(), Error> {
-        let image_id = image.id.clone().ok_or(Error::InvalidId)?;
-        let user_id = user.id.clone().ok_or(Error::InvalidId)?;
-
-        let query = format!("relate {}->upload->{};", user_id, image_id);
-        self.client.query(query).await?;
-
-        Ok(())
-    }
-
     pub fn tag<'b>(
         &self,
         image: &Image,
@@ -0,0 +1,58 @@
+use chrono::Utc;
+use surrealdb::{engine::remote::ws::Client, sql::Datetime, Surreal};
+
+use crate::{
+    errors::Error,
+    jwt::hash_token,
+    models::{refresh::Refresh, user::User},
+};
+
+use super::{builder::record, Database};
+
+pub struct RefreshDB<'a> {
+    pub client: &'a Surreal<Client>,
+    pub db: &'a Database,
+}
+
+impl<'a> RefreshDB<'a> {
+    pub async fn create(&self, user: &User) -> Result<(Refresh, String), Error> {
+        let user_id = record(user.id.as_ref().ok_or(Error::InvalidId)?)?;
+        let (token, hash, expire_at) = Refresh::generate()?;
+
+        let mut res = self
+            .client
+            .query("create refresh set user = $user, hash = $hash, created_at = $created_at, expire_at = $expire_at")
+            .bind(("user", user_id))
+            .bind(("hash", hash))
+            .bind(("created_at", Datetime::from(Utc::now())))
+            .bind(("expire_at", Datetime::from(expire_at)))
+            .await?;
+
+        let refresh: Option<Refresh> = res.take(0)?;
+        let refresh = refresh.ok_or(Error::DatabaseError)?;
+
+        Ok((refresh, token))
+    }
+
+    // fetch and delete in one statement, so a token can only be used once
+    pub async fn consume(&self, token: &str) -> Result<Option<Refresh>, Error> {
+        let mut res = self
+            .client
+            .query("delete refresh where hash = $hash and expire_at > time::now() return before")
+            .bind(("hash", hash_token(token)))
+            .await?;
+
+        Ok(res.take(0)?)
+    }
+
+    pub async fn delete_user(&self, user: &User) -> Result<(), Error> {
+        let user_id = record(user.id.as_ref().ok_or(Error::InvalidId)?)?;
+
+        self.client
+            .query("delete refresh where user = $user")
+            .bind(("user", user_id))
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,76 @@
+use chrono::{DateTime, Utc};
+use surrealdb::{engine::remote::ws::Client, sql::Datetime, Surreal};
+
+use crate::{
+    errors::Error,
+    jwt::random_token,
+    models::{image::Rating, resumable::Resumable, user::User},
+};
+
+use super::{builder::record, Database};
+
+pub struct ResumableDB<'a> {
+    pub client: &'a Surreal<Client>,
+    pub db: &'a Database,
+}
+
+impl<'a> ResumableDB<'a> {
+    pub async fn create(
+        &self,
+        user: &User,
+        length: u64,
+        content_type: String,
+        private: bool,
+        rating: Rating,
+        expire_at: DateTime<Utc>,
+    ) -> Result<Resumable, Error> {
+        let user_id = record(user.id.as_ref().ok_or(Error::InvalidId)?)?;
+
+        let mut res = self
+            .client
+            .query("create resumable set key = $key, user = $user, length = $length, content_type = $content_type, private = $private, rating = $rating, created_at = $created_at, expire_at = $expire_at")
+            .bind(("key", random_token()?))
+            .bind(("user", user_id))
+            .bind(("length", length))
+            .bind(("content_type", content_type))
+            .bind(("private", private))
+            .bind(("rating", rating))
+            .bind(("created_at", Datetime::from(Utc::now())))
+            .bind(("expire_at", Datetime::from(expire_at)))
+            .await?;
+
+        let resumable: Option<Resumable> = res.take(0)?;
+
+        resumable.ok_or(Error::DatabaseError)
+    }
+
+    pub async fn get(&self, key: &str) -> Result<Option<Resumable>, Error> {
+        let mut res = self
+            .client
+            .query("select * from resumable where key = $key")
+            .bind(("key", key))
+            .await?;
+
+        Ok(res.take(0)?)
+    }
+
+    pub async fn expired(&self) -> Result<Vec<Resumable>, Error> {
+        let mut res = self
+            .client
+            .query("select * from resumable where expire_at < time::now()")
+            .await?;
+
+        Ok(res.take(0)?)
+    }
+
+    pub async fn delete(&self, resumable: Resumable) -> Result<(), Error> {
+        let id = resumable.id.ok_or(Error::InvalidId)?;
+
+        self.client
+            .query("delete $resumable")
+            .bind(("resumable", record(&id)?))
+            .await?;
+
+        Ok(())
+    }
+}
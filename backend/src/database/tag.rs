@@ -7,7 +7,7 @@ use crate::{
     models::{tag::Tag, user::User, image::Image},
 };
 
-use super::{Database, Session};
+use super::{builder::record, Database, Session};
 
 pub struct TagDB<'a> {
     pub client: &'a Surreal<Client>,
@@ -21,6 +21,17 @@ impl<'a> TagDB<'a> {
     }
 
     pub async fn get(&self, name: &String, category: &String) -> Result<Option<Tag>, Error> {
+        if let Some(tag) = self.find(name, category).await? {
+            return Ok(Some(tag));
+        }
+
+        match self.db.alias().get(name, category).await? {
+            Some(alias) => self.from_id(&alias.tag).await,
+            None => Ok(None),
+        }
+    }
+
+    pub async fn find(&self, name: &String, category: &String) -> Result<Option<Tag>, Error> {
         let mut res = self
             .client
             .query("select *, <-upload<-user.name as user from tag where name = $name and category = $category")
@@ -31,14 +42,29 @@ impl<'a> TagDB<'a> {
         Ok(res.take(0)?)
     }
 
+    pub async fn from_id(&self, id: &str) -> Result<Option<Tag>, Error> {
+        let mut res = self
+            .client
+            .query("select * from $tag")
+            .bind(("tag", record(id)?))
+            .await?;
+
+        Ok(res.take(0)?)
+    }
+
     pub async fn search(&self, category: &String, name: &String) -> Result<Vec<Tag>, Error> {
         let limit = 32;
         let query = format!(
-            "select * from tag where category = /^{}/ and name = /^{}/ order by count desc limit {};",
-            category, name, limit
+            "select * from tag where string::startsWith(category, $category) and string::startsWith(name, $name) order by count desc limit {};",
+            limit
         );
 
-        let mut res = self.client.query(query).await?;
+        let mut res = self
+            .client
+            .query(query)
+            .bind(("category", category))
+            .bind(("name", name))
+            .await?;
         let tags: Vec<Tag> = res.take(0)?;
         let tags = try_join_all(tags.into_iter().map(|t| self.user(t))).await?; 
 
@@ -46,11 +72,14 @@ impl<'a> TagDB<'a> {
     }
 
     pub async fn user_set(&self, tag: &Tag, user: &User) -> Result<Tag, Error> {
-        let tag_id = tag.id.clone().ok_or(Error::InvalidId)?;
-        let user_id = user.id.clone().ok_or(Error::InvalidId)?;
+        let tag_id = record(tag.id.as_ref().ok_or(Error::InvalidId)?)?;
+        let user_id = record(user.id.as_ref().ok_or(Error::InvalidId)?)?;
 
-        let query = format!("relate {}->upload->{};", user_id, tag_id);
-        self.client.query(query).await?;
+        self.client
+            .query("relate $user->upload->$tag;")
+            .bind(("user", user_id))
+            .bind(("tag", tag_id))
+            .await?;
 
         let tag = self.get(&tag.name, &tag.category)
             .await?
@@ -61,10 +90,12 @@ impl<'a> TagDB<'a> {
 
     pub async fn delete(&self, tag: Tag) -> Result<(), Error> {
         let tag = self
-            .get(&tag.name, &tag.category)
+            .find(&tag.name, &tag.category)
             .await?
             .ok_or(Error::TagNotFound)?;
 
+        self.db.alias().delete_tag(&tag).await?;
+
         let id = tag.id.ok_or(Error::TagNotFound)?;
         let (_, id) = id.split_at(4);
 
@@ -107,6 +138,85 @@ impl<'a> TagDB<'a> {
         Ok(tag)
     }
 
+    pub async fn implied(&self, tag: &Tag) -> Result<Vec<Tag>, Error> {
+        let id = record(tag.id.as_ref().ok_or(Error::InvalidId)?)?;
+
+        let mut res = self
+            .client
+            .query("select ->implies->tag.* as implied from $tag")
+            .bind(("tag", id))
+            .await?;
+
+        #[derive(Deserialize)]
+        struct Tags {
+            implied: Vec<Tag>,
+        }
+
+        let tags: Option<Tags> = res.take(0)?;
+        let tags = match tags {
+            Some(t) => t.implied,
+            None => vec![],
+        };
+
+        Ok(tags)
+    }
+
+    // tags and everything they imply, transitively
+    pub async fn implications(&self, tags: Vec<Tag>) -> Result<Vec<Tag>, Error> {
+        let mut result: Vec<Tag> = vec![];
+        let mut pending = tags;
+
+        while let Some(tag) = pending.pop() {
+            if result.iter().any(|t| t.id == tag.id) {
+                continue;
+            }
+
+            pending.extend(self.implied(&tag).await?);
+            result.push(tag);
+        }
+
+        Ok(result)
+    }
+
+    pub async fn imply(&self, tag: &Tag, implied: &Tag) -> Result<(), Error> {
+        if self.implied(tag).await?.iter().any(|t| t.id == implied.id) {
+            return Err(Error::ImplicationExists);
+        }
+
+        let closure = self.implications(vec![implied.clone()]).await?;
+        if closure.iter().any(|t| t.id == tag.id) {
+            return Err(Error::ImplicationCycle);
+        }
+
+        let tag_id = record(tag.id.as_ref().ok_or(Error::InvalidId)?)?;
+        let implied_id = record(implied.id.as_ref().ok_or(Error::InvalidId)?)?;
+
+        self.client
+            .query("relate $tag->implies->$implied;")
+            .bind(("tag", tag_id))
+            .bind(("implied", implied_id))
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn unimply(&self, tag: &Tag, implied: &Tag) -> Result<(), Error> {
+        if !self.implied(tag).await?.iter().any(|t| t.id == implied.id) {
+            return Err(Error::ImplicationNotFound);
+        }
+
+        let tag_id = record(tag.id.as_ref().ok_or(Error::InvalidId)?)?;
+        let implied_id = record(implied.id.as_ref().ok_or(Error::InvalidId)?)?;
+
+        self.client
+            .query("delete implies where in = $tag and out = $implied;")
+            .bind(("tag", tag_id))
+            .bind(("implied", implied_id))
+            .await?;
+
+        Ok(())
+    }
+
     pub fn update<'b>(
         &self,
         tag: &Tag,
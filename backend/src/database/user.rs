@@ -1,12 +1,16 @@
 use serde::Deserialize;
 use surrealdb::{engine::remote::ws::Client, Surreal};
 
+use crate::config;
 use crate::errors::Error;
-use crate::models::image::Image;
+use crate::filter::Term;
+use crate::models::image::{Image, Rating};
 use crate::models::tag::Tag;
-use crate::models::user::User;
+use crate::models::user::{Role, User};
+use crate::pattern::Pattern;
+use crate::routes::search::PatternTag;
 
-use super::Database;
+use super::{builder::record, Database};
 
 pub struct UserDB<'a> {
     pub client: &'a Surreal<Client>,
@@ -15,14 +19,26 @@ pub struct UserDB<'a> {
 
 impl<'a> UserDB<'a> {
     pub async fn get(&self, name: &String) -> Result<Option<User>, Error> {
-        let query = format!("select * from user where name = '{}'", name);
-
-        let mut res = self.client.query(query).await?;
+        let mut res = self
+            .client
+            .query("select * from user where name = $name")
+            .bind(("name", name))
+            .await?;
         let user: Option<User> = res.take(0)?;
 
         Ok(user)
     }
 
+    pub async fn from_id(&self, id: &str) -> Result<Option<User>, Error> {
+        let mut res = self
+            .client
+            .query("select * from $user")
+            .bind(("user", record(id)?))
+            .await?;
+
+        Ok(res.take(0)?)
+    }
+
     pub async fn from_image(&self, image: &Image) -> Result<User, Error> {
         let id = image.id.clone().ok_or(Error::InvalidId)?;
 
@@ -74,7 +90,11 @@ impl<'a> UserDB<'a> {
             return Err(Error::UserExists);
         }
 
-        let user = User::hash(name, password)?;
+        let mut user = User::hash(name, password)?;
+        if config::get().admins.contains(&user.name) {
+            user.role = Role::Admin;
+        }
+
         let user: User = self.client.create("user").content(user).await?;
 
         Ok(user)
@@ -85,13 +105,68 @@ impl<'a> UserDB<'a> {
             return Err(Error::UserNotFound);
         }
 
-        let query = format!("select * from user where name = '{}'", name);
-
-        let mut res = self.client.query(query).await?;
+        let mut res = self
+            .client
+            .query("select * from user where name = $name")
+            .bind(("name", name))
+            .await?;
         let user: Option<User> = res.take(0)?;
         let user = user.ok_or(Error::UserNotFound)?;
         user.verify(password)?;
 
         Ok(user)
     }
+
+    pub async fn set_role(&self, user: &User, role: Role) -> Result<(), Error> {
+        let id = record(user.id.as_ref().ok_or(Error::InvalidId)?)?;
+
+        self.client
+            .query("update $user set role = $role")
+            .bind(("user", id))
+            .bind(("role", role))
+            .await?;
+
+        Ok(())
+    }
+
+    // the configured admins that already exist, the others are promoted at signup
+    pub async fn promote(&self, admins: &[String]) -> Result<(), Error> {
+        for name in admins {
+            if let Some(user) = self.get(name).await? {
+                if user.role != Role::Admin {
+                    self.set_role(&user, Role::Admin).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn set_ratings(&self, user: &User, ratings: &[Rating]) -> Result<(), Error> {
+        let id = record(user.id.as_ref().ok_or(Error::InvalidId)?)?;
+
+        self.client
+            .query("update $user set ratings = $ratings")
+            .bind(("user", id))
+            .bind(("ratings", ratings))
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn set_blacklist(
+        &self,
+        user: &User,
+        blacklist: Option<&Pattern<Term<PatternTag>>>,
+    ) -> Result<(), Error> {
+        let id = record(user.id.as_ref().ok_or(Error::InvalidId)?)?;
+
+        self.client
+            .query("update $user set blacklist = $blacklist")
+            .bind(("user", id))
+            .bind(("blacklist", blacklist))
+            .await?;
+
+        Ok(())
+    }
 }
@@ -1,52 +1,104 @@
 use axum::{http::StatusCode, response::IntoResponse, Json};
 use serde_json::json;
 
+use crate::query::ParseError;
+
 #[derive(Debug)]
 pub enum Error {
     ServerCreate,
+    Config,
     DatabaseConnection,
     DatabaseError,
     MissingCredential,
     WrongCredential,
     InvalidToken,
+    Forbidden,
     MissingField,
     ImageExists,
     ImageNotFound,
     TagExists,
     TagNotFound,
+    AliasExists,
+    AliasNotFound,
+    ImplicationExists,
+    ImplicationNotFound,
+    ImplicationCycle,
     UserExists,
     UserNotFound,
+    ApiKeyExists,
+    ApiKeyNotFound,
     Hashing,
     Upload,
+    TooLarge,
+    UploadNotFound,
+    UploadExpired,
+    UploadOffset,
+    TusVersion,
+    Cdn,
     Serialize,
     InvalidId,
     NotImplemented,
     WrongType,
+    ConflictingField,
+    InvalidQuery(ParseError),
 }
 
-impl IntoResponse for Error {
-    fn into_response(self) -> axum::response::Response {
-        let (status, message) = match self {
+impl Error {
+    pub fn describe(&self) -> (StatusCode, &'static str) {
+        match self {
             Error::ServerCreate => (StatusCode::INTERNAL_SERVER_ERROR, "Server Creation"),
+            Error::Config => (StatusCode::INTERNAL_SERVER_ERROR, "Configuration"),
             Error::DatabaseConnection => (StatusCode::INTERNAL_SERVER_ERROR, "Database Connection"),
             Error::DatabaseError => (StatusCode::INTERNAL_SERVER_ERROR, "Database Error"),
             Error::MissingCredential => (StatusCode::BAD_REQUEST, "Missing Credential"),
             Error::WrongCredential => (StatusCode::UNAUTHORIZED, "Wrong Credential"),
             Error::InvalidToken => (StatusCode::BAD_REQUEST, "Invalid Token"),
+            Error::Forbidden => (StatusCode::FORBIDDEN, "Forbidden"),
             Error::MissingField => (StatusCode::BAD_REQUEST, "Missing Field"),
             Error::ImageExists => (StatusCode::BAD_REQUEST, "Image already exists"),
             Error::ImageNotFound => (StatusCode::BAD_REQUEST, "Image not found"),
             Error::TagExists => (StatusCode::BAD_REQUEST, "Tag already exists"),
             Error::TagNotFound => (StatusCode::BAD_REQUEST, "Tag not found"),
+            Error::AliasExists => (StatusCode::BAD_REQUEST, "Alias already exists"),
+            Error::AliasNotFound => (StatusCode::BAD_REQUEST, "Alias not found"),
+            Error::ImplicationExists => (StatusCode::BAD_REQUEST, "Implication already exists"),
+            Error::ImplicationNotFound => (StatusCode::BAD_REQUEST, "Implication not found"),
+            Error::ImplicationCycle => (StatusCode::BAD_REQUEST, "Implication would create a cycle"),
             Error::UserExists => (StatusCode::BAD_REQUEST, "User already exists"),
             Error::UserNotFound => (StatusCode::BAD_REQUEST, "User not found"),
+            Error::ApiKeyExists => (StatusCode::BAD_REQUEST, "Api key already exists"),
+            Error::ApiKeyNotFound => (StatusCode::BAD_REQUEST, "Api key not found"),
             Error::Hashing => (StatusCode::INTERNAL_SERVER_ERROR, "Hashing password"),
             Error::Upload => (StatusCode::BAD_REQUEST, "Upload Error"),
+            Error::TooLarge => (StatusCode::PAYLOAD_TOO_LARGE, "File too large"),
+            Error::UploadNotFound => (StatusCode::NOT_FOUND, "Upload not found"),
+            Error::UploadExpired => (StatusCode::GONE, "Upload expired"),
+            Error::UploadOffset => (StatusCode::CONFLICT, "Wrong upload offset"),
+            Error::TusVersion => (StatusCode::PRECONDITION_FAILED, "Unsupported tus version"),
+            Error::Cdn => (StatusCode::BAD_GATEWAY, "Cdn Error"),
             Error::Serialize => (StatusCode::INTERNAL_SERVER_ERROR, "Serialize"),
             Error::InvalidId => (StatusCode::BAD_REQUEST, "Invalid Id"),
             Error::NotImplemented => (StatusCode::INTERNAL_SERVER_ERROR, "Not Implemented"),
             Error::WrongType => (StatusCode::BAD_REQUEST, "Wrong Type"),
-        };
+            Error::ConflictingField => (StatusCode::BAD_REQUEST, "Conflicting Field"),
+            Error::InvalidQuery(_) => (StatusCode::BAD_REQUEST, "Invalid Query"),
+        }
+    }
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> axum::response::Response {
+        if let Error::InvalidQuery(e) = &self {
+            let body = Json(json!({
+                "error": "Invalid Query",
+                "reason": e.reason,
+                "position": e.position,
+            }));
+
+            return (StatusCode::BAD_REQUEST, body).into_response();
+        }
+
+        let (status, message) = self.describe();
 
         let body = Json(json!({
             "error": message,
@@ -0,0 +1,384 @@
+use chrono::{Days, NaiveDate, NaiveTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use surrealdb::sql::{Datetime, Value};
+
+use crate::{
+    database::builder::QueryBuilder, errors::Error, models::image::Rating, pattern::Condition,
+};
+
+/* Metadata filters
+
+   width:>=1920  height:<1080      pixels, missing on files without dimensions
+   ratio:16:9  ratio:>1.5          width / height, equality is approximate
+   filesize:<2MB                   B, KB, MB, GB (1024 based)
+   date:2024-01-01..2024-06-30     upload day, both ends included
+   mime:image/png                  content type, a trailing * matches a prefix
+   rating:safe  rating:<=q         safe < questionable < explicit, or their first letter
+
+   Numbers and dates take =, <, <=, >, >= or a range a..b.
+*/
+
+const RATIO_TOLERANCE: f64 = 0.01;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum Op {
+    #[serde(rename = "=")]
+    Eq,
+    #[serde(rename = "<")]
+    Lt,
+    #[serde(rename = "<=")]
+    Le,
+    #[serde(rename = ">")]
+    Gt,
+    #[serde(rename = ">=")]
+    Ge,
+}
+
+impl Op {
+    fn symbol(&self) -> &'static str {
+        match self {
+            Op::Eq => "=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum Bound<T> {
+    Compare { op: Op, value: T },
+    Range { from: T, to: T },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "field", content = "value", rename_all = "lowercase")]
+pub enum Filter {
+    Width(Bound<u32>),
+    Height(Bound<u32>),
+    Ratio(Bound<f64>),
+    Filesize(Bound<u64>),
+    Date(Bound<NaiveDate>),
+    Mime(String),
+    // the ratings shown
+    Rating(Vec<Rating>),
+}
+
+// a pattern item, either a tag or a filter on the image itself
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum Term<T> {
+    Filter(Filter),
+    Tag(T),
+}
+
+impl<T: Condition> Condition for Term<T> {
+    fn condition(self, set: &str, builder: &mut QueryBuilder) -> Result<String, Error> {
+        match self {
+            Term::Tag(t) => t.condition(set, builder),
+            Term::Filter(f) => Ok(f.condition(builder)),
+        }
+    }
+
+    fn negate(self, set: &str, builder: &mut QueryBuilder) -> Result<String, Error> {
+        match self {
+            Term::Tag(t) => t.negate(set, builder),
+            Term::Filter(f) => Ok(format!("(({}) == false)", f.condition(builder))),
+        }
+    }
+}
+
+impl<T: PartialOrd> Bound<T> {
+    fn contains(&self, x: &T) -> bool {
+        match self {
+            Bound::Compare { op, value } => match op {
+                Op::Eq => x == value,
+                Op::Lt => x < value,
+                Op::Le => x <= value,
+                Op::Gt => x > value,
+                Op::Ge => x >= value,
+            },
+            Bound::Range { from, to } => from <= x && x <= to,
+        }
+    }
+}
+
+impl<T: Into<Value>> Bound<T> {
+    fn condition(self, field: &str, builder: &mut QueryBuilder) -> String {
+        match self {
+            Bound::Compare { op, value } => {
+                format!("{} {} {}", field, op.symbol(), builder.bind(value))
+            }
+            Bound::Range { from, to } => format!(
+                "({} >= {} && {} <= {})",
+                field,
+                builder.bind(from),
+                field,
+                builder.bind(to)
+            ),
+        }
+    }
+}
+
+impl Filter {
+    // None when the key is not a filter, so it is read as a tag category
+    pub fn parse(key: &str, value: &str) -> Option<Result<Self, &'static str>> {
+        let filter = match key {
+            "width" => bound(value, number).map(Filter::Width),
+            "height" => bound(value, number).map(Filter::Height),
+            "ratio" => bound(value, ratio).map(Filter::Ratio),
+            "filesize" => bound(value, filesize).map(Filter::Filesize),
+            "date" => bound(value, date).map(Filter::Date),
+            "mime" => Ok(Filter::Mime(value.to_string())),
+            "rating" => bound(value, Rating::parse).map(|b| {
+                let ratings = Rating::ALL.into_iter().filter(|r| b.contains(r));
+                Filter::Rating(ratings.collect())
+            }),
+            _ => return None,
+        };
+
+        Some(filter)
+    }
+
+    fn condition(self, builder: &mut QueryBuilder) -> String {
+        match self {
+            Filter::Width(b) => b.condition("media.width", builder),
+            Filter::Height(b) => b.condition("media.height", builder),
+            // images without media or with a zero height never match
+            Filter::Ratio(Bound::Compare { op: Op::Eq, value }) => format!(
+                "(media.height > 0 && math::abs((<float> media.width / media.height) - {}) < {})",
+                builder.bind(value),
+                RATIO_TOLERANCE
+            ),
+            Filter::Ratio(b) => format!(
+                "(media.height > 0 && {})",
+                b.condition("(<float> media.width / media.height)", builder)
+            ),
+            Filter::Filesize(b) => b.condition("media.size", builder),
+            Filter::Date(b) => days(b, builder),
+            Filter::Mime(m) => match m.strip_suffix('*') {
+                Some(prefix) => format!(
+                    "string::startsWith(content_type, {})",
+                    builder.bind(prefix.to_string())
+                ),
+                None => format!("content_type = {}", builder.bind(m)),
+            },
+            Filter::Rating(ratings) => {
+                let ratings: Vec<String> = ratings.iter().map(|r| r.name().to_string()).collect();
+
+                // images without a rating have the default one
+                format!(
+                    "(rating ?? {}) inside {}",
+                    builder.bind(Rating::default().name().to_string()),
+                    builder.bind(ratings)
+                )
+            }
+        }
+    }
+}
+
+// a day covers every upload from its midnight to the next one
+fn days(bound: Bound<NaiveDate>, builder: &mut QueryBuilder) -> String {
+    let mut start = |day: NaiveDate| {
+        let time = Utc.from_utc_datetime(&day.and_time(NaiveTime::default()));
+        builder.bind(Datetime::from(time))
+    };
+    let next = |day: NaiveDate| day.checked_add_days(Days::new(1)).unwrap_or(day);
+
+    match bound {
+        Bound::Compare { op, value } => match op {
+            Op::Eq => format!(
+                "(created_at >= {} && created_at < {})",
+                start(value),
+                start(next(value))
+            ),
+            Op::Lt => format!("created_at < {}", start(value)),
+            Op::Le => format!("created_at < {}", start(next(value))),
+            Op::Gt => format!("created_at >= {}", start(next(value))),
+            Op::Ge => format!("created_at >= {}", start(value)),
+        },
+        Bound::Range { from, to } => format!(
+            "(created_at >= {} && created_at < {})",
+            start(from),
+            start(next(to))
+        ),
+    }
+}
+
+fn bound<T>(value: &str, parse: fn(&str) -> Option<T>) -> Result<Bound<T>, &'static str> {
+    let operators = [
+        (">=", Op::Ge),
+        ("<=", Op::Le),
+        (">", Op::Gt),
+        ("<", Op::Lt),
+        ("=", Op::Eq),
+    ];
+
+    if let Some((op, rest)) = operators
+        .iter()
+        .find_map(|(s, op)| value.strip_prefix(s).map(|rest| (*op, rest)))
+    {
+        let value = parse(rest).ok_or("Invalid filter value")?;
+        return Ok(Bound::Compare { op, value });
+    }
+
+    if let Some((from, to)) = value.split_once("..") {
+        let from = parse(from).ok_or("Invalid filter value")?;
+        let to = parse(to).ok_or("Invalid filter value")?;
+        return Ok(Bound::Range { from, to });
+    }
+
+    let value = parse(value).ok_or("Invalid filter value")?;
+    Ok(Bound::Compare { op: Op::Eq, value })
+}
+
+fn number(value: &str) -> Option<u32> {
+    value.parse().ok()
+}
+
+// 16:9 or 1.78
+fn ratio(value: &str) -> Option<f64> {
+    match value.split_once(':') {
+        Some((w, h)) => {
+            let (w, h): (f64, f64) = (w.parse().ok()?, h.parse().ok()?);
+            (h > 0.0).then_some(w / h)
+        }
+        None => value.parse().ok(),
+    }
+}
+
+fn filesize(value: &str) -> Option<u64> {
+    let value = value.to_lowercase();
+    let units = [("gb", 1 << 30), ("mb", 1 << 20), ("kb", 1 << 10), ("b", 1)];
+
+    let (number, unit) = units
+        .iter()
+        .find_map(|(s, unit)| value.strip_suffix(s).map(|n| (n, *unit)))
+        .unwrap_or((value.as_str(), 1));
+
+    let number: f64 = number.parse().ok()?;
+    (number >= 0.0).then_some((number * unit as f64) as u64)
+}
+
+fn date(value: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(key: &str, value: &str) -> Filter {
+        Filter::parse(key, value).unwrap().unwrap()
+    }
+
+    #[test]
+    fn not_a_filter() {
+        assert!(Filter::parse("artist", "foo").is_none());
+    }
+
+    #[test]
+    fn open_bounds() {
+        assert!(matches!(
+            filter("width", ">=1920"),
+            Filter::Width(Bound::Compare {
+                op: Op::Ge,
+                value: 1920
+            })
+        ));
+        assert!(matches!(
+            filter("height", "<1080"),
+            Filter::Height(Bound::Compare {
+                op: Op::Lt,
+                value: 1080
+            })
+        ));
+        assert!(matches!(
+            filter("width", "800"),
+            Filter::Width(Bound::Compare {
+                op: Op::Eq,
+                value: 800
+            })
+        ));
+        assert!(matches!(
+            filter("filesize", ">1.5kb"),
+            Filter::Filesize(Bound::Compare {
+                op: Op::Gt,
+                value: 1536
+            })
+        ));
+    }
+
+    #[test]
+    fn closed_bounds() {
+        assert!(matches!(
+            filter("width", "10..20"),
+            Filter::Width(Bound::Range { from: 10, to: 20 })
+        ));
+        assert!(matches!(
+            filter("filesize", "1MB..2MB"),
+            Filter::Filesize(Bound::Range {
+                from: 1048576,
+                to: 2097152
+            })
+        ));
+
+        let Filter::Date(Bound::Range { from, to }) = filter("date", "2024-01-01..2024-06-30")
+        else {
+            panic!("not a date range");
+        };
+        assert_eq!(from, NaiveDate::from_ymd_opt(2024, 1, 1).unwrap());
+        assert_eq!(to, NaiveDate::from_ymd_opt(2024, 6, 30).unwrap());
+    }
+
+    #[test]
+    fn ratios() {
+        let Filter::Ratio(Bound::Compare { op: Op::Eq, value }) = filter("ratio", "16:9") else {
+            panic!("not a ratio");
+        };
+        assert!((value - 16.0 / 9.0).abs() < 1e-9);
+
+        assert!(matches!(
+            filter("ratio", ">1.5"),
+            Filter::Ratio(Bound::Compare { op: Op::Gt, .. })
+        ));
+        assert!(Filter::parse("ratio", "1:0").unwrap().is_err());
+    }
+
+    #[test]
+    fn ratings() {
+        let ratings = |value: &str| match filter("rating", value) {
+            Filter::Rating(r) => r,
+            _ => panic!("not a rating"),
+        };
+
+        assert_eq!(ratings("safe"), vec![Rating::Safe]);
+        assert_eq!(ratings("<=q"), vec![Rating::Safe, Rating::Questionable]);
+        assert_eq!(ratings(">s"), vec![Rating::Questionable, Rating::Explicit]);
+        assert_eq!(ratings("s..e"), Rating::ALL.to_vec());
+    }
+
+    #[test]
+    fn invalid_values() {
+        for value in ["", ">=", "abc", "..20", "10..", "-5"] {
+            assert!(bound(value, number).is_err(), "{:?}", value);
+        }
+        assert!(Filter::parse("date", "2024-13-01").unwrap().is_err());
+        assert!(Filter::parse("rating", "x").unwrap().is_err());
+    }
+
+    #[test]
+    fn contains() {
+        let range = Bound::Range { from: 10, to: 20 };
+        assert!(range.contains(&10) && range.contains(&20));
+        assert!(!range.contains(&9) && !range.contains(&21));
+
+        let below = Bound::Compare {
+            op: Op::Lt,
+            value: 10,
+        };
+        assert!(below.contains(&9) && !below.contains(&10));
+    }
+}
@@ -1,17 +1,25 @@
-use std::{env, sync::OnceLock};
+use std::{collections::BTreeSet, env, sync::OnceLock};
 
 use axum::{
     async_trait,
-    extract::FromRequestParts,
+    extract::{FromRef, FromRequestParts},
     headers::{authorization::Bearer, Authorization},
     http::request::Parts,
     RequestPartsExt, TypedHeader,
 };
 use chrono::{Duration, Utc};
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use ring::{
+    digest::{digest, SHA256},
+    rand::{SecureRandom, SystemRandom},
+};
 use serde::{Deserialize, Serialize};
 
-use crate::errors::Error;
+use crate::{
+    database::Database,
+    errors::Error,
+    models::{apikey::Scope, user::Role},
+};
 
 struct Keys {
     encoding: EncodingKey,
@@ -20,10 +28,19 @@ struct Keys {
 
 static KEYS: OnceLock<Keys> = OnceLock::new();
 
+const ACCESS_MINUTES: i64 = 15;
+const TOKEN_SIZE: usize = 32;
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String,
     pub exp: i64,
+    #[serde(default)]
+    pub role: Role,
+    #[serde(default = "Scope::all")]
+    pub scopes: BTreeSet<Scope>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -31,16 +48,26 @@ pub struct Token {
     pub access_token: String,
     pub token_type: String,
     pub expire: i64,
+    pub refresh_token: String,
+    pub refresh_expire: i64,
 }
 
 #[async_trait]
 impl<S> FromRequestParts<S> for Claims
 where
+    Database: FromRef<S>,
     S: Send + Sync,
 {
     type Rejection = Error;
 
-    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(key) = parts.headers.get(API_KEY_HEADER) {
+            let key = key.to_str().map_err(|_| Error::InvalidToken)?;
+            let db = Database::from_ref(state);
+
+            return Claims::from_key(&db, key).await;
+        }
+
         let TypedHeader(Authorization(bearer)) = parts
             .extract::<TypedHeader<Authorization<Bearer>>>()
             .await
@@ -51,11 +78,59 @@ where
 }
 
 impl Claims {
-    pub fn new(sub: String) -> Claims {
-        let exp = Utc::now() + Duration::days(1);
+    pub fn new(sub: String, role: Role) -> Claims {
+        let exp = Utc::now() + Duration::minutes(ACCESS_MINUTES);
         let exp = exp.timestamp();
 
-        Claims { sub, exp }
+        Claims {
+            sub,
+            exp,
+            role,
+            scopes: Scope::all(),
+        }
+    }
+
+    async fn from_key(db: &Database, key: &str) -> Result<Claims, Error> {
+        let apikey = db.apikey().get(key).await?.ok_or(Error::InvalidToken)?;
+
+        let user = db
+            .user()
+            .from_id(&apikey.user)
+            .await?
+            .ok_or(Error::InvalidToken)?;
+
+        db.apikey().used(&apikey).await?;
+
+        Ok(Claims {
+            sub: user.name,
+            exp: 0,
+            role: user.role,
+            scopes: apikey.scopes,
+        })
+    }
+
+    pub fn require(&self, role: Role) -> Result<(), Error> {
+        if self.role >= role {
+            Ok(())
+        } else {
+            Err(Error::Forbidden)
+        }
+    }
+
+    pub fn permit(&self, scope: Scope) -> Result<(), Error> {
+        if self.scopes.contains(&scope) {
+            Ok(())
+        } else {
+            Err(Error::Forbidden)
+        }
+    }
+
+    pub fn owner_or(&self, owner: &str, role: Role) -> Result<(), Error> {
+        if self.sub == owner {
+            Ok(())
+        } else {
+            self.require(role)
+        }
     }
 
     fn keys() -> Keys {
@@ -67,7 +142,7 @@ impl Claims {
         }
     }
 
-    pub fn encode(&self) -> Result<Token, Error> {
+    pub fn encode(&self, refresh_token: String, refresh_expire: i64) -> Result<Token, Error> {
         let access_token = encode(
             &Header::default(),
             self,
@@ -79,6 +154,8 @@ impl Claims {
             access_token,
             token_type: "Bearer".to_string(),
             expire: self.exp,
+            refresh_token,
+            refresh_expire,
         })
     }
 
@@ -94,3 +171,48 @@ impl Claims {
         Ok(claims)
     }
 }
+
+pub fn random_token() -> Result<String, Error> {
+    let mut bytes = [0u8; TOKEN_SIZE];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| Error::Hashing)?;
+
+    Ok(hex(&bytes))
+}
+
+// tokens are random, a fast hash is enough to store them
+pub fn hash_token(token: &str) -> String {
+    hex(digest(&SHA256, token.as_bytes()).as_ref())
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claims(scopes: &[Scope]) -> Claims {
+        Claims {
+            scopes: scopes.iter().copied().collect(),
+            ..Claims::new("user".to_string(), Role::default())
+        }
+    }
+
+    #[test]
+    fn scopes_are_not_ordered() {
+        let upload = claims(&[Scope::Upload]);
+        assert!(upload.permit(Scope::Upload).is_ok());
+        assert!(upload.permit(Scope::Read).is_err());
+        assert!(upload.permit(Scope::Full).is_err());
+
+        let full = claims(&[Scope::Full]);
+        assert!(full.permit(Scope::Read).is_err());
+
+        let session = Claims::new("user".to_string(), Role::default());
+        assert!(session.permit(Scope::Read).is_ok());
+        assert!(session.permit(Scope::Full).is_ok());
+    }
+}
@@ -1,61 +1,119 @@
+mod cdn;
+mod config;
 mod database;
 mod errors;
+mod filter;
 mod jwt;
 mod models;
 mod routes;
+mod upload;
 mod pattern;
+mod query;
 
 use axum::{
-    routing::{post, put},
+    extract::DefaultBodyLimit,
+    http::HeaderName,
+    routing::{get, patch, post, put},
     Router, Server,
 };
 use database::Database;
 use dotenv::dotenv;
 use errors::Error;
-use std::{env, net::SocketAddr};
+use models::searchresponse::HIDDEN_HEADER;
 
 use tower_http::cors::CorsLayer;
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     dotenv().ok();
-    let url = env::var("DATABASE_URL").map_err(|_| Error::DatabaseConnection)?;
+    let config = config::init()?;
+    let database = &config.database;
 
-    let db = Database::new(url)
+    let db = Database::new(database.url.clone())
         .await?
-        .signin("root", "root")
+        .signin(&database.username, &database.password)
         .await?
-        .connect("booru", "booru")
+        .connect(&database.namespace, &database.name)
         .await?;
 
+    db.user().promote(&config.admins).await?;
+
+    // credentials are allowed, so the headers have to be listed
+    let exposed: Vec<HeaderName> = routes::tus::HEADERS
+        .into_iter()
+        .map(HeaderName::from_static)
+        .chain([HIDDEN_HEADER])
+        .collect();
+
     let app = Router::new()
         .nest(
             "/api/v1",
             Router::new()
                 .route("/login", post(routes::user::login))
                 .route("/signup", post(routes::user::signup))
+                .route("/logout", post(routes::user::logout))
+                .route("/logout/all", post(routes::user::logout_all))
+                .route("/token/refresh", post(routes::user::refresh))
+                .route("/user/role", patch(routes::user::role))
+                .route("/user/ratings", patch(routes::user::ratings))
+                .route(
+                    "/user/blacklist",
+                    get(routes::user::blacklist).put(routes::user::set_blacklist)
+                )
+                .route(
+                    "/apikey",
+                    put(routes::apikey::create)
+                        .post(routes::apikey::post)
+                        .delete(routes::apikey::delete)
+                )
                 .route(
                     "/image",
+                    // the upload enforces its own size limit while streaming
                     put(routes::image::create)
+                        .route_layer(DefaultBodyLimit::disable())
                         .post(routes::image::post)
                         .delete(routes::image::delete)
                         .patch(routes::image::update)
                 )
+                .route(
+                    "/image/batch",
+                    put(routes::image::batch).route_layer(DefaultBodyLimit::disable())
+                )
+                .route(
+                    "/upload",
+                    post(routes::tus::create).options(routes::tus::options)
+                )
+                .route(
+                    "/upload/:key",
+                    patch(routes::tus::append).head(routes::tus::status)
+                )
                 .route(
                     "/tag",
                     put(routes::tag::create)
                         .post(routes::tag::post)
                         .delete(routes::tag::delete)
                 )
+                .route(
+                    "/alias",
+                    put(routes::alias::create)
+                        .delete(routes::alias::delete)
+                )
+                .route(
+                    "/implication",
+                    put(routes::implication::create)
+                        .delete(routes::implication::delete)
+                )
+                .route("/gc", post(routes::gc::collect))
+                .route("/migrate/sha256", post(routes::migrate::sha256))
+                .route("/migrate/media", post(routes::migrate::media))
+                .route("/migrate/thumbs", post(routes::migrate::thumbs))
                 .route("/search/image", post(routes::search::image))
                 .route("/search/tag", post(routes::search::tag)),
         )
-        .layer(CorsLayer::very_permissive())
+        .layer(CorsLayer::very_permissive().expose_headers(exposed))
         .with_state(db);
 
-    let addr = SocketAddr::from(([127, 0, 0, 1], 5000));
-
-    Server::bind(&addr)
+    Server::bind(&config.bind)
         .serve(app.into_make_service())
         .await
         .map_err(|_| Error::ServerCreate)?;
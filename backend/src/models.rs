@@ -1,5 +1,15 @@
+pub mod alias;
+pub mod apikey;
+pub mod apikeyresponse;
+pub mod batchresponse;
+pub mod gcresponse;
 pub mod image;
+pub mod media;
 pub mod tag;
 pub mod user;
+pub mod refresh;
+pub mod resumable;
 pub mod imageresponse; 
+pub mod migrateresponse;
+pub mod searchresponse;
 pub mod tagresponse;
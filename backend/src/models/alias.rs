@@ -0,0 +1,10 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Alias {
+    #[serde(skip_serializing)]
+    pub id: Option<String>,
+    pub name: String,
+    pub category: String,
+    pub tag: String,
+}
@@ -0,0 +1,33 @@
+use std::collections::BTreeSet;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    Read,
+    Upload,
+    Full,
+}
+
+impl Scope {
+    // what a login session, or a key created without scopes, is allowed
+    pub fn all() -> BTreeSet<Scope> {
+        BTreeSet::from([Scope::Read, Scope::Upload, Scope::Full])
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ApiKey {
+    #[serde(skip_serializing)]
+    pub id: Option<String>,
+    pub user: String,
+    pub name: String,
+    pub prefix: String,
+    pub hash: String,
+    pub scopes: BTreeSet<Scope>,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub last_used: Option<DateTime<Utc>>,
+}
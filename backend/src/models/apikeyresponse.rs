@@ -0,0 +1,54 @@
+use std::collections::BTreeSet;
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::Error;
+
+use super::apikey::{ApiKey, Scope};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiKeyResponse {
+    pub name: String,
+    pub prefix: String,
+    pub scopes: BTreeSet<Scope>,
+    pub created_at: DateTime<Utc>,
+    pub last_used: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub key: Option<String>,
+}
+
+impl ApiKeyResponse {
+    pub fn new(apikey: ApiKey) -> Self {
+        Self {
+            name: apikey.name,
+            prefix: apikey.prefix,
+            scopes: apikey.scopes,
+            created_at: apikey.created_at,
+            last_used: apikey.last_used,
+            key: None,
+        }
+    }
+
+    // the key is only shown once, when it is created
+    pub fn with_key(apikey: ApiKey, key: String) -> Self {
+        Self {
+            key: Some(key),
+            ..Self::new(apikey)
+        }
+    }
+}
+
+impl IntoResponse for ApiKeyResponse {
+    fn into_response(self) -> Response {
+        match serde_json::to_string(&self) {
+            Ok(data) => (StatusCode::OK, data).into_response(),
+            Err(_) => Error::Serialize.into_response(),
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+
+use crate::errors::Error;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchStatus {
+    Created,
+    Duplicate,
+    Failed,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchResponse {
+    pub filename: String,
+    pub status: BatchStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+impl BatchResponse {
+    pub fn new(filename: String, status: BatchStatus, hash: Option<String>) -> Self {
+        Self {
+            filename,
+            status,
+            hash,
+            error: None,
+        }
+    }
+
+    pub fn failed(filename: String, hash: Option<String>, error: Error) -> Self {
+        let (_, message) = error.describe();
+
+        Self {
+            error: Some(message.to_string()),
+            ..Self::new(filename, BatchStatus::Failed, hash)
+        }
+    }
+}
@@ -0,0 +1,25 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::Error;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GcResponse {
+    pub dry_run: bool,
+    // hashes stored on the cdn without an image record
+    pub orphans: Vec<String>,
+    pub deleted: Vec<String>,
+    pub failed: Vec<String>,
+}
+
+impl IntoResponse for GcResponse {
+    fn into_response(self) -> Response {
+        match serde_json::to_string(&self) {
+            Ok(data) => (StatusCode::OK, data).into_response(),
+            Err(_) => Error::Serialize.into_response(),
+        }
+    }
+}
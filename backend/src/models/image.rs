@@ -1,15 +1,58 @@
-use axum::body::Bytes;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use super::{tag::Tag, user::User};
+use super::{media::Media, tag::Tag, user::User};
+
+// ordered from the least to the most explicit
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Rating {
+    Safe,
+    // also the rating of images uploaded before ratings existed
+    #[default]
+    Questionable,
+    Explicit,
+}
+
+impl Rating {
+    pub const ALL: [Rating; 3] = [Rating::Safe, Rating::Questionable, Rating::Explicit];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rating::Safe => "safe",
+            Rating::Questionable => "questionable",
+            Rating::Explicit => "explicit",
+        }
+    }
+
+    // the full name or its first letter
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|r| value == r.name() || value == &r.name()[..1])
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Image {
     #[serde(skip_serializing)]
     pub id: Option<String>,
     pub hash: String,
+    // missing on images uploaded before it was computed, until backfilled
+    #[serde(default)]
+    pub sha256: Option<String>,
     pub created_at: DateTime<Utc>,
     pub content_type: String,
+    // missing on images uploaded before it was kept, until backfilled
+    #[serde(default)]
+    pub media: Option<Media>,
+    #[serde(default)]
+    pub rating: Rating,
+    // only served by the cdn with a signed url
+    #[serde(default)]
+    pub private: bool,
+    // where the image was found, given at upload
+    #[serde(default)]
+    pub source: Option<String>,
     #[serde(skip_serializing, skip_deserializing)]
     pub tags: Vec<Tag>,
     #[serde(skip_serializing, skip_deserializing)]
@@ -17,14 +60,17 @@ pub struct Image {
 }
 
 impl Image {
-    pub fn new(data: &Bytes, content_type: String) -> Self {
-        let hash = format!("{:x}", md5::compute(data));
-
+    pub fn new(hash: String, sha256: String, content_type: String) -> Self {
         Self {
             id: None,
             hash,
+            sha256: Some(sha256),
             created_at: Utc::now(),
             content_type,
+            media: None,
+            rating: Rating::default(),
+            private: false,
+            source: None,
             tags: vec![],
             user: String::new(),
         }
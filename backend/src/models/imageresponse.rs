@@ -3,17 +3,29 @@ use axum::{
     response::{IntoResponse, Response},
 };
 use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
 use serde::{Deserialize, Serialize};
 
-use crate::errors::Error;
+use crate::{cdn, config, errors::Error};
 
-use super::{image::Image, tagresponse::TagResponse};
+use super::{
+    image::{Image, Rating},
+    media::Media,
+    tagresponse::TagResponse,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ImageResponse {
     pub hash: String,
+    pub sha256: Option<String>,
     pub url: String,
     pub thumb: String,
+    pub variants: BTreeMap<String, String>,
+    pub resize: String,
+    pub media: Option<Media>,
+    pub rating: Rating,
+    pub private: bool,
+    pub source: Option<String>,
     pub created_at: DateTime<Utc>,
     pub tags: Vec<TagResponse>,
     pub user: String,
@@ -21,14 +33,42 @@ pub struct ImageResponse {
 
 impl ImageResponse {
     pub fn new(image: Image) -> Self {
-        let url = format!("http://localhost:4000/{}", image.hash);
-        let thumb = format!("http://localhost:4000/thumb/{}", image.hash);
+        let cdn = &config::get().cdn.public_url;
+        let url = format!("{}/{}", cdn, image.hash);
+        let thumb = format!("{}/thumb/{}", cdn, image.hash);
+        let resize = format!("{}/resize/{}", cdn, image.hash);
+        let variants = config::get()
+            .cdn
+            .presets
+            .iter()
+            .map(|p| (p.clone(), format!("{}/thumb/{}/{}", cdn, p, image.hash)))
+            .collect();
         let tags = image.tags.into_iter().map(TagResponse::new).collect();
 
+        let (url, thumb, resize, variants) = match image.private {
+            false => (url, thumb, resize, variants),
+            true => (
+                cdn::signed_url(url, &image.hash),
+                cdn::signed_url(thumb, &image.hash),
+                cdn::signed_url(resize, &image.hash),
+                variants
+                    .into_iter()
+                    .map(|(p, v)| (p, cdn::signed_url(v, &image.hash)))
+                    .collect(),
+            ),
+        };
+
         Self {
             hash: image.hash,
+            sha256: image.sha256,
             url,
             thumb,
+            variants,
+            resize,
+            media: image.media,
+            rating: image.rating,
+            private: image.private,
+            source: image.source,
             created_at: image.created_at,
             tags,
             user: image.user,
@@ -0,0 +1,14 @@
+use serde::{Deserialize, Serialize};
+
+// read by the cdn when the file is stored
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Media {
+    pub size: u64,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    // animated images only, duration in milliseconds
+    pub frames: Option<u32>,
+    pub duration: Option<u64>,
+    pub color: Option<String>,
+    pub icc_profile: bool,
+}
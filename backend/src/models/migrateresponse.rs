@@ -0,0 +1,23 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::Error;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MigrateResponse {
+    pub updated: usize,
+    // hashes of the images left as they were
+    pub failed: Vec<String>,
+}
+
+impl IntoResponse for MigrateResponse {
+    fn into_response(self) -> Response {
+        match serde_json::to_string(&self) {
+            Ok(data) => (StatusCode::OK, data).into_response(),
+            Err(_) => Error::Serialize.into_response(),
+        }
+    }
+}
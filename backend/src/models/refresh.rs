@@ -0,0 +1,30 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::Error,
+    jwt::{hash_token, random_token},
+};
+
+const REFRESH_DAYS: i64 = 30;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Refresh {
+    #[serde(skip_serializing)]
+    pub id: Option<String>,
+    pub user: String,
+    pub hash: String,
+    pub created_at: DateTime<Utc>,
+    pub expire_at: DateTime<Utc>,
+}
+
+impl Refresh {
+    // returns the token given to the client, only its hash is stored
+    pub fn generate() -> Result<(String, String, DateTime<Utc>), Error> {
+        let token = random_token()?;
+        let hash = hash_token(&token);
+        let expire_at = Utc::now() + Duration::days(REFRESH_DAYS);
+
+        Ok((token, hash, expire_at))
+    }
+}
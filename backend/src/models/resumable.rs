@@ -0,0 +1,21 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::image::Rating;
+
+// an upload in progress, its data is in the upload temp_dir
+// and the size of the file is the offset reached so far
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Resumable {
+    #[serde(skip_serializing)]
+    pub id: Option<String>,
+    pub key: String,
+    pub user: String,
+    pub length: u64,
+    pub content_type: String,
+    pub private: bool,
+    #[serde(default)]
+    pub rating: Rating,
+    pub created_at: DateTime<Utc>,
+    pub expire_at: DateTime<Utc>,
+}
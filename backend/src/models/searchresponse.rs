@@ -0,0 +1,32 @@
+use axum::{
+    http::{HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+
+use crate::errors::Error;
+
+use super::imageresponse::ImageResponse;
+
+pub const HIDDEN_HEADER: HeaderName = HeaderName::from_static("x-hidden-count");
+
+// the images stay a bare array, the count is only a header for older clients
+#[derive(Debug)]
+pub struct SearchResponse {
+    pub images: Vec<ImageResponse>,
+    // results left out by the blacklist of the user
+    pub hidden: usize,
+}
+
+impl IntoResponse for SearchResponse {
+    fn into_response(self) -> Response {
+        match serde_json::to_string(&self.images) {
+            Ok(data) => (
+                StatusCode::OK,
+                [(HIDDEN_HEADER, HeaderValue::from(self.hidden))],
+                data,
+            )
+                .into_response(),
+            Err(_) => Error::Serialize.into_response(),
+        }
+    }
+}
@@ -7,12 +7,24 @@ use ring::{
 };
 use serde::{Deserialize, Serialize};
 
-use crate::errors::Error;
+use crate::{errors::Error, filter::Term, pattern::Pattern, routes::search::PatternTag};
+
+use super::image::Rating;
 
 const ITERATIONS: u32 = 100_000;
 const SALT_SIZE: usize = 64;
 const CREDENTIAL_SIZE: usize = digest::SHA512_OUTPUT_LEN;
 
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    Member,
+    Janitor,
+    Moderator,
+    Admin,
+}
+
 #[serde_with::serde_as]
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct User {
@@ -23,6 +35,14 @@ pub struct User {
     pub salt: [u8; SALT_SIZE],
     #[serde_as(as = "serde_with::hex::Hex")]
     pub hash: [u8; CREDENTIAL_SIZE],
+    #[serde(default)]
+    pub role: Role,
+    // applied to searches without a rating filter, empty shows every rating
+    #[serde(default)]
+    pub ratings: Vec<Rating>,
+    // images matching it are left out of searches
+    #[serde(default)]
+    pub blacklist: Option<Pattern<Term<PatternTag>>>,
 }
 
 impl User {
@@ -48,6 +68,9 @@ impl User {
             name,
             salt,
             hash,
+            role: Role::default(),
+            ratings: vec![],
+            blacklist: None,
         })
     }
 
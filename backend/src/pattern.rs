@@ -2,12 +2,36 @@ use futures::{
     future::{try_join_all, BoxFuture},
     FutureExt,
 };
-use serde::{de::Error, Deserialize, Deserializer};
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize};
 use std::{future::Future, sync::Arc};
 
+use crate::{database::builder::QueryBuilder, errors::Error, models::tag::Tag};
+
+// how an item of a pattern is written in the where clause
+pub trait Condition {
+    fn condition(self, set: &str, builder: &mut QueryBuilder) -> Result<String, Error>;
+
+    fn negate(self, set: &str, builder: &mut QueryBuilder) -> Result<String, Error>
+    where
+        Self: Sized,
+    {
+        Ok(format!("(({}) == false)", self.condition(set, builder)?))
+    }
+}
+
+impl Condition for Tag {
+    fn condition(self, set: &str, builder: &mut QueryBuilder) -> Result<String, Error> {
+        Ok(format!("{} inside {}", builder.record(&self.to_string())?, set))
+    }
+
+    fn negate(self, set: &str, builder: &mut QueryBuilder) -> Result<String, Error> {
+        Ok(format!("{} notinside {}", builder.record(&self.to_string())?, set))
+    }
+}
+
 // Waiting for this: https://github.com/serde-rs/serde/pull/2403
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(tag = "type", content = "data")]
 pub enum Tagged<T> {
     NOT(Box<Pattern<T>>),
@@ -17,7 +41,7 @@ pub enum Tagged<T> {
     OR(Vec<Pattern<T>>),
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(untagged)]
 pub enum Pattern<T> {
     Item(T),
@@ -41,29 +65,31 @@ impl<'a, T> Tagged<T>
 where
     T: Send + 'a,
 {
-    fn serialize(self, set: &str) -> String
-        where T: ToString
+    fn serialize(self, set: &str, builder: &mut QueryBuilder) -> Result<String, Error>
+        where T: Condition
     {
-        match self {
+        let s = match self {
             Self::NOT(x) => match *x {
-                Pattern::Item(y) => format!("{} notinside {}", y.to_string(), set),
-                _ => format!("(({}) == false)", x.serialize(set)),
+                Pattern::Item(y) => y.negate(set, builder)?,
+                _ => format!("(({}) == false)", x.serialize(set, builder)?),
             },
-            Self::AND(v) => Self::join(v, "&&", set),
-            Self::OR(v) => Self::join(v, "||", set),
-        }
+            Self::AND(v) => Self::join(v, "&&", set, builder)?,
+            Self::OR(v) => Self::join(v, "||", set, builder)?,
+        };
+
+        Ok(s)
     }
 
-    fn join(patterns: Vec<Pattern<T>>, separator: &str, set: &str) -> String 
-        where T: ToString
+    fn join(patterns: Vec<Pattern<T>>, separator: &str, set: &str, builder: &mut QueryBuilder) -> Result<String, Error>
+        where T: Condition
     {
         let s = patterns
             .into_iter()
-            .map(|p| p.serialize(set))
-            .reduce(|a, b| format!("{} {} {}", a, separator, b))
-            .unwrap();
+            .map(|p| p.serialize(set, builder))
+            .collect::<Result<Vec<String>, Error>>()?
+            .join(&format!(" {} ", separator));
 
-        format!("({})", s)
+        Ok(format!("({})", s))
     }
 
     fn convert<U, F, Fut>(self, f: Arc<F>) -> BoxFuture<'a, Result<Tagged<U>, ()>>
@@ -98,12 +124,21 @@ impl<'a, T> Pattern<T>
 where
     T: Send + 'a,
 {
-    pub fn serialize(self, set: &str) -> String 
-        where T: ToString
+    pub fn serialize(self, set: &str, builder: &mut QueryBuilder) -> Result<String, Error>
+        where T: Condition
     {
         match self {
-            Self::Item(x) => format!("{} inside {}", x.to_string(), set),
-            Self::Tagged(x) => x.serialize(set),
+            Self::Item(x) => x.condition(set, builder),
+            Self::Tagged(x) => x.serialize(set, builder),
+        }
+    }
+
+    // whether an item anywhere in the pattern matches
+    pub fn any(&self, f: &impl Fn(&T) -> bool) -> bool {
+        match self {
+            Self::Item(x) => f(x),
+            Self::Tagged(Tagged::NOT(x)) => x.any(f),
+            Self::Tagged(Tagged::AND(v) | Tagged::OR(v)) => v.iter().any(|p| p.any(f)),
         }
     }
 
@@ -124,3 +159,29 @@ where
         .boxed()
     }
 }
+
+impl<T> Pattern<Option<T>> {
+    // without the missing items, a clause that depends on one is dropped as a whole
+    pub fn prune(self) -> Option<Pattern<T>> {
+        match self {
+            Pattern::Item(x) => x.map(Pattern::Item),
+            Pattern::Tagged(Tagged::NOT(x)) => {
+                x.prune().map(|p| Pattern::Tagged(Tagged::NOT(Box::new(p))))
+            }
+            Pattern::Tagged(Tagged::AND(v)) => {
+                let v = v
+                    .into_iter()
+                    .map(Pattern::prune)
+                    .collect::<Option<Vec<_>>>()?;
+                Some(Pattern::Tagged(Tagged::AND(v)))
+            }
+            Pattern::Tagged(Tagged::OR(v)) => {
+                let mut v: Vec<_> = v.into_iter().filter_map(Pattern::prune).collect();
+                match v.len() {
+                    0 | 1 => v.pop(),
+                    _ => Some(Pattern::Tagged(Tagged::OR(v))),
+                }
+            }
+        }
+    }
+}
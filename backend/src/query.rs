@@ -0,0 +1,293 @@
+use serde::Serialize;
+
+use crate::{
+    filter::{Filter, Term},
+    pattern::{Pattern, Tagged},
+    routes::search::PatternTag,
+};
+
+/* Query language
+
+   artist:foo character:bar     both tags                 (AND)
+   -rating:explicit             without the tag           (NOT)
+   ~cat ~dog                    at least one of the tags  (OR)
+   a | b                        either side               (OR)
+   (a | b) c                    grouping
+
+   A tag without category uses DEFAULT_CATEGORY.
+   Categories named like a filter (width:>=1920, see filter.rs) are filters.
+*/
+
+pub const DEFAULT_CATEGORY: &str = "general";
+
+type Item = Term<PatternTag>;
+
+#[derive(Debug, Serialize)]
+pub struct ParseError {
+    pub position: usize,
+    pub reason: &'static str,
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+pub fn parse(input: &str) -> Result<Option<Pattern<Item>>, ParseError> {
+    let mut parser = Parser {
+        chars: input.chars().collect(),
+        pos: 0,
+    };
+
+    parser.skip_whitespace();
+    if parser.peek().is_none() {
+        return Ok(None);
+    }
+
+    let pattern = parser.or()?;
+
+    match parser.peek() {
+        None => Ok(Some(pattern)),
+        Some(')') => Err(parser.error("Unmatched ')'")),
+        Some(_) => Err(parser.error("Unexpected character")),
+    }
+}
+
+fn collapse(
+    mut patterns: Vec<Pattern<Item>>,
+    f: fn(Vec<Pattern<Item>>) -> Tagged<Item>,
+) -> Pattern<Item> {
+    if patterns.len() == 1 {
+        patterns.remove(0)
+    } else {
+        Pattern::Tagged(f(patterns))
+    }
+}
+
+fn is_word(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, '(' | ')' | '|')
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn error(&self, reason: &'static str) -> ParseError {
+        ParseError {
+            position: self.pos,
+            reason,
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().map_or(false, char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn or(&mut self) -> Result<Pattern<Item>, ParseError> {
+        let mut patterns = vec![self.and()?];
+
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            self.skip_whitespace();
+            patterns.push(self.and()?);
+        }
+
+        Ok(collapse(patterns, Tagged::OR))
+    }
+
+    fn and(&mut self) -> Result<Pattern<Item>, ParseError> {
+        let mut all = vec![];
+        let mut any = vec![];
+
+        loop {
+            match self.peek() {
+                None | Some(')') | Some('|') => break,
+                Some('~') => {
+                    self.pos += 1;
+                    any.push(self.not()?);
+                }
+                Some(_) => all.push(self.not()?),
+            }
+            self.skip_whitespace();
+        }
+
+        if !any.is_empty() {
+            all.push(collapse(any, Tagged::OR));
+        }
+
+        if all.is_empty() {
+            return Err(self.error("Expected tag"));
+        }
+
+        Ok(collapse(all, Tagged::AND))
+    }
+
+    fn not(&mut self) -> Result<Pattern<Item>, ParseError> {
+        if self.peek() == Some('-') {
+            self.pos += 1;
+            let inner = self.not()?;
+            return Ok(Pattern::Tagged(Tagged::NOT(Box::new(inner))));
+        }
+
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Pattern<Item>, ParseError> {
+        match self.peek() {
+            Some('(') => {
+                let open = self.pos;
+                self.pos += 1;
+                self.skip_whitespace();
+
+                let pattern = self.or()?;
+
+                if self.peek() != Some(')') {
+                    return Err(ParseError {
+                        position: open,
+                        reason: "Unclosed '('",
+                    });
+                }
+                self.pos += 1;
+
+                Ok(pattern)
+            }
+            Some(c) if is_word(c) && c != '~' => self.tag(),
+            _ => Err(self.error("Expected tag")),
+        }
+    }
+
+    fn tag(&mut self) -> Result<Pattern<Item>, ParseError> {
+        let start = self.pos;
+        let mut colon = None;
+
+        while let Some(c) = self.peek() {
+            if !is_word(c) {
+                break;
+            }
+            if c == ':' && colon.is_none() {
+                colon = Some(self.pos);
+            }
+            self.pos += 1;
+        }
+
+        let word = |from: usize, to: usize| self.chars[from..to].iter().collect::<String>();
+
+        let tag = match colon {
+            Some(c) if c == start => {
+                return Err(ParseError {
+                    position: c,
+                    reason: "Empty category",
+                })
+            }
+            Some(c) if c + 1 == self.pos => {
+                return Err(ParseError {
+                    position: c + 1,
+                    reason: "Empty tag name",
+                })
+            }
+            Some(c) => {
+                let (category, name) = (word(start, c), word(c + 1, self.pos));
+
+                match Filter::parse(&category, &name) {
+                    Some(Ok(filter)) => return Ok(Pattern::Item(Term::Filter(filter))),
+                    Some(Err(reason)) => {
+                        return Err(ParseError {
+                            position: c + 1,
+                            reason,
+                        })
+                    }
+                    None => PatternTag { category, name },
+                }
+            }
+            None => PatternTag {
+                category: DEFAULT_CATEGORY.to_string(),
+                name: word(start, self.pos),
+            },
+        };
+
+        Ok(Pattern::Item(Term::Tag(tag)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a compact form of the pattern, to compare with the expected one
+    fn show(pattern: &Pattern<Item>) -> String {
+        let join = |patterns: &[Pattern<Item>], separator: &str| {
+            let patterns: Vec<String> = patterns.iter().map(show).collect();
+            format!("({})", patterns.join(separator))
+        };
+
+        match pattern {
+            Pattern::Item(Term::Tag(tag)) => format!("{}:{}", tag.category, tag.name),
+            Pattern::Item(Term::Filter(_)) => "filter".to_string(),
+            Pattern::Tagged(Tagged::NOT(p)) => format!("-{}", show(p)),
+            Pattern::Tagged(Tagged::AND(v)) => join(v, " & "),
+            Pattern::Tagged(Tagged::OR(v)) => join(v, " | "),
+        }
+    }
+
+    fn parsed(input: &str) -> String {
+        show(&parse(input).unwrap().unwrap())
+    }
+
+    fn error(input: &str) -> (usize, &'static str) {
+        let error = parse(input).unwrap_err();
+        (error.position, error.reason)
+    }
+
+    #[test]
+    fn empty() {
+        assert!(parse("").unwrap().is_none());
+        assert!(parse("   ").unwrap().is_none());
+    }
+
+    #[test]
+    fn categories() {
+        assert_eq!(parsed("a"), "general:a");
+        assert_eq!(parsed("artist:foo"), "artist:foo");
+        assert_eq!(parsed("width:>=1920"), "filter");
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(parsed("a b | c"), "((general:a & general:b) | general:c)");
+        assert_eq!(parsed("a | b c"), "(general:a | (general:b & general:c))");
+        assert_eq!(
+            parsed("-a ~b ~c d"),
+            "(-general:a & general:d & (general:b | general:c))"
+        );
+    }
+
+    #[test]
+    fn nesting() {
+        assert_eq!(parsed("((a))"), "general:a");
+        assert_eq!(parsed("a (b | c)"), "(general:a & (general:b | general:c))");
+        assert_eq!(parsed("-(a | b)"), "-(general:a | general:b)");
+        assert_eq!(
+            parsed("artist:x ((a | b) c)"),
+            "(artist:x & ((general:a | general:b) & general:c))"
+        );
+    }
+
+    #[test]
+    fn unbalanced() {
+        assert_eq!(error("(a b"), (0, "Unclosed '('"));
+        assert_eq!(error("a (b | (c)"), (2, "Unclosed '('"));
+        assert_eq!(error("a b)"), (3, "Unmatched ')'"));
+        assert_eq!(error("()"), (1, "Expected tag"));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error("a | "), (4, "Expected tag"));
+        assert_eq!(error(":a"), (0, "Empty category"));
+        assert_eq!(error("a:"), (2, "Empty tag name"));
+        assert_eq!(error("width:abc"), (6, "Invalid filter value"));
+    }
+}
@@ -1,4 +1,10 @@
+pub mod alias;
+pub mod apikey;
+pub mod gc;
 pub mod image;
+pub mod implication;
+pub mod migrate;
 pub mod tag;
 pub mod user;
 pub mod search;
+pub mod tus;
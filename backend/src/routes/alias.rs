@@ -0,0 +1,76 @@
+use axum::{extract::State, Json};
+use serde::Deserialize;
+
+use crate::{
+    database::Database,
+    errors::Error,
+    jwt::Claims,
+    models::{apikey::Scope, tagresponse::TagResponse, user::Role},
+};
+
+#[derive(Deserialize)]
+pub struct Target {
+    name: String,
+    category: String,
+}
+
+#[derive(Deserialize)]
+pub struct Create {
+    name: String,
+    category: String,
+    tag: Target,
+}
+
+pub async fn create(
+    claims: Claims,
+    State(db): State<Database>,
+    Json(query): Json<Create>,
+) -> Result<TagResponse, Error> {
+    claims.permit(Scope::Full)?;
+    claims.require(Role::Janitor)?;
+
+    let Create { name, category, tag } = query;
+
+    if db.tag().find(&name, &category).await?.is_some() {
+        return Err(Error::TagExists);
+    }
+
+    if db.alias().get(&name, &category).await?.is_some() {
+        return Err(Error::AliasExists);
+    }
+
+    let tag = db
+        .tag()
+        .get(&tag.name, &tag.category)
+        .await?
+        .ok_or(Error::TagNotFound)?;
+
+    db.alias().create(&name, &category, &tag).await?;
+
+    Ok(TagResponse::new(tag))
+}
+
+#[derive(Deserialize)]
+pub struct Delete {
+    name: String,
+    category: String,
+}
+
+pub async fn delete(
+    claims: Claims,
+    State(db): State<Database>,
+    Json(query): Json<Delete>,
+) -> Result<(), Error> {
+    claims.permit(Scope::Full)?;
+    claims.require(Role::Janitor)?;
+
+    let alias = db
+        .alias()
+        .get(&query.name, &query.category)
+        .await?
+        .ok_or(Error::AliasNotFound)?;
+
+    db.alias().delete(alias).await?;
+
+    Ok(())
+}
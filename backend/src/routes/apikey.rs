@@ -0,0 +1,75 @@
+use std::collections::BTreeSet;
+
+use axum::{extract::State, Json};
+use serde::Deserialize;
+
+use crate::{
+    database::Database,
+    errors::Error,
+    jwt::Claims,
+    models::{apikey::Scope, apikeyresponse::ApiKeyResponse},
+};
+
+#[derive(Deserialize)]
+pub struct Create {
+    name: String,
+    #[serde(default = "Scope::all")]
+    scopes: BTreeSet<Scope>,
+}
+
+pub async fn create(
+    claims: Claims,
+    State(db): State<Database>,
+    Json(query): Json<Create>,
+) -> Result<ApiKeyResponse, Error> {
+    claims.permit(Scope::Full)?;
+
+    if query.name.is_empty() || query.scopes.is_empty() {
+        return Err(Error::MissingField);
+    }
+
+    let user = db.user().get(&claims.sub).await?.ok_or(Error::UserNotFound)?;
+
+    if db.apikey().from_name(&user, &query.name).await?.is_some() {
+        return Err(Error::ApiKeyExists);
+    }
+
+    let (apikey, key) = db.apikey().create(&user, &query.name, query.scopes).await?;
+
+    Ok(ApiKeyResponse::with_key(apikey, key))
+}
+
+pub async fn post(
+    claims: Claims,
+    State(db): State<Database>,
+) -> Result<Json<Vec<ApiKeyResponse>>, Error> {
+    let user = db.user().get(&claims.sub).await?.ok_or(Error::UserNotFound)?;
+
+    let apikeys = db.apikey().from_user(&user).await?;
+    let apikeys = apikeys.into_iter().map(ApiKeyResponse::new).collect();
+
+    Ok(Json(apikeys))
+}
+
+#[derive(Deserialize)]
+pub struct Delete {
+    name: String,
+}
+
+pub async fn delete(
+    claims: Claims,
+    State(db): State<Database>,
+    Json(query): Json<Delete>,
+) -> Result<(), Error> {
+    claims.permit(Scope::Full)?;
+
+    let user = db.user().get(&claims.sub).await?.ok_or(Error::UserNotFound)?;
+
+    let apikey = db
+        .apikey()
+        .from_name(&user, &query.name)
+        .await?
+        .ok_or(Error::ApiKeyNotFound)?;
+
+    db.apikey().delete(apikey).await
+}
@@ -0,0 +1,68 @@
+use std::collections::BTreeSet;
+
+use axum::{extract::State, Json};
+use chrono::Utc;
+use serde::Deserialize;
+
+use crate::{
+    cdn, config,
+    database::Database,
+    errors::Error,
+    jwt::Claims,
+    models::{apikey::Scope, gcresponse::GcResponse, user::Role},
+};
+
+#[derive(Deserialize)]
+pub struct Collect {
+    #[serde(default = "dry_run")]
+    dry_run: bool,
+}
+
+fn dry_run() -> bool {
+    true
+}
+
+pub async fn collect(
+    claims: Claims,
+    State(db): State<Database>,
+    Json(query): Json<Collect>,
+) -> Result<GcResponse, Error> {
+    claims.permit(Scope::Full)?;
+    claims.require(Role::Admin)?;
+
+    let stored = cdn::stored().await?;
+    let images: BTreeSet<String> = db.image().hashes().await?.into_iter().collect();
+
+    // an upload reaches the cdn before its record is created
+    let before = Utc::now().timestamp() - config::get().cdn.gc_grace;
+
+    let orphans: Vec<String> = stored
+        .into_iter()
+        .filter(|(hash, modified)| *modified < before && !images.contains(hash))
+        .map(|(hash, _)| hash)
+        .collect();
+
+    let mut deleted = vec![];
+    let mut failed = vec![];
+
+    if !query.dry_run {
+        for hash in &orphans {
+            // created while the orphans were listed
+            if db.image().get(hash).await?.is_some() {
+                continue;
+            }
+
+            match cdn::delete(hash).await {
+                Ok(_) => deleted.push(hash.clone()),
+                Err(_) => failed.push(hash.clone()),
+            }
+        }
+    }
+
+    Ok(GcResponse {
+        dry_run: query.dry_run,
+        orphans,
+        deleted,
+        failed,
+    })
+}
@@ -1,106 +1,286 @@
+use std::collections::HashSet;
+
 use axum::{
-    body::Bytes,
     extract::{multipart::Field, Multipart, State},
     Json,
 };
 use axum_macros::debug_handler;
-use futures::future::try_join_all;
-use reqwest::{
-    multipart::{Form, Part},
-    Client,
-};
+use futures::{future::try_join_all, stream, StreamExt};
 use serde::Deserialize;
 use surrealdb::sql::statements::{BeginStatement, CommitStatement};
 
 use crate::{
+    cdn, config,
     database::Database,
     errors::Error,
     jwt::Claims,
-    models::{image::Image, imageresponse::ImageResponse, tag::Tag, tagresponse::TagResponse},
+    models::{
+        apikey::Scope,
+        batchresponse::{BatchResponse, BatchStatus},
+        image::{Image, Rating},
+        imageresponse::ImageResponse,
+        tag::Tag,
+        tagresponse::TagResponse,
+        user::Role,
+    },
+    upload::{self, Upload},
 };
 
-async fn upload(image: &Image, data: Bytes) -> Result<(), Error> {
-    let hash = image.hash.clone();
-    let part = Part::bytes(data.to_vec())
-        .file_name(hash)
-        .mime_str(&image.content_type)
-        .map_err(|_| Error::WrongType)?;
-
-    let multipart = Form::new().part("file", part);
-
-    Client::new()
-        .post("http://localhost:4000")
-        .multipart(multipart)
-        .send()
-        .await
-        .map_err(|_| Error::Upload)?
-        .error_for_status()
-        .map_err(|_| Error::Upload)?;
+// form values are short, tags are a small json list
+const MAX_FIELD: usize = 16 * 1024;
 
-    Ok(())
+// a field other than the file, never larger than MAX_FIELD
+async fn text(mut field: Field<'_>) -> Result<String, Error> {
+    let mut data = vec![];
+
+    while let Some(chunk) = field.chunk().await.map_err(|_| Error::MissingField)? {
+        if data.len() + chunk.len() > MAX_FIELD {
+            return Err(Error::TooLarge);
+        }
+
+        data.extend_from_slice(&chunk);
+    }
+
+    String::from_utf8(data).map_err(|_| Error::WrongType)
 }
 
-async fn parse_field(field: Field<'_>) -> Option<(String, String, String, Bytes)> {
-    let name = match field.name() {
-        Some(n) => n.to_string(),
-        None => return None,
-    };
+pub async fn create(
+    claims: Claims,
+    State(db): State<Database>,
+    mut multipart: Multipart,
+) -> Result<String, Error> {
+    claims.permit(Scope::Upload)?;
 
-    let filename = match field.file_name() {
-        Some(n) => n.to_string(),
-        None => return None,
-    };
+    let mut private = false;
+    let mut rating = Rating::default();
+    let mut source = None;
+    let mut tags = vec![];
+    let mut upload = None;
 
-    let content_type = match field.content_type() {
-        Some(n) => n.to_string(),
-        None => return None,
-    };
+    while let Ok(Some(field)) = multipart.next_field().await {
+        match field.name() {
+            Some("private") => {
+                let value = text(field).await?;
+                private = value.parse().map_err(|_| Error::WrongType)?;
+            }
+            Some("rating") => {
+                let value = text(field).await?;
+                rating = Rating::parse(&value).ok_or(Error::WrongType)?;
+            }
+            Some("source") => source = Some(text(field).await?),
+            // json list of {name, category}
+            Some("tags") => {
+                let value = text(field).await?;
+                tags = serde_json::from_str(&value).map_err(|_| Error::WrongType)?;
+            }
+            // written to a temporary file, never held in memory
+            Some("image") => upload = Some(upload::receive(field).await?),
+            _ => continue,
+        }
+    }
 
-    let data = match field.bytes().await {
-        Ok(n) => n,
-        Err(_) => return None,
+    let upload = upload.ok_or(Error::MissingField)?;
+    let image = Image {
+        private,
+        rating,
+        source,
+        ..Image::new(
+            upload.hash.clone(),
+            upload.sha256.clone(),
+            upload.content_type.clone(),
+        )
     };
 
-    Some((name, filename, content_type, data))
+    let image = store(&db, &claims.sub, image, &upload, &tags).await?;
+
+    Ok(image.hash)
 }
 
-pub async fn create(
+struct Pending {
+    filename: String,
+    upload: Result<Upload, Error>,
+    tags: Vec<TagResponse>,
+    rating: Rating,
+    source: Option<String>,
+    // the same file earlier in the batch
+    duplicate: bool,
+}
+
+/* Batch upload
+
+   Every "image" field is a file, the "tags" (json list of {name, category}),
+   "rating" and "source" fields that follow it apply to that file.
+   Files are received one after the other, then stored upload.concurrency at a time.
+   A batch over upload.batch_files files or upload.batch_size bytes is rejected.
+*/
+pub async fn batch(
     claims: Claims,
     State(db): State<Database>,
     mut multipart: Multipart,
-) -> Result<String, Error> {
-    // fix large image
+) -> Result<Json<Vec<BatchResponse>>, Error> {
+    claims.permit(Scope::Upload)?;
+
+    let config = &config::get().upload;
+    let mut pending: Vec<Pending> = vec![];
+    let mut size = 0;
+
     while let Ok(Some(field)) = multipart.next_field().await {
-        let Some((name, _, content_type, data)) = parse_field(field).await else {
+        let name = field.name().map(str::to_string);
+
+        if name.as_deref() == Some("image") {
+            if pending.len() >= config.batch_files {
+                return Err(Error::TooLarge);
+            }
+
+            let filename = field.file_name().unwrap_or_default().to_string();
+            let upload = upload::receive(field).await;
+
+            // each file is already bounded by max_size
+            size += upload.as_ref().map_or(0, |u| u.size);
+            if size > config.batch_size {
+                return Err(Error::TooLarge);
+            }
+
+            pending.push(Pending {
+                filename,
+                upload,
+                tags: vec![],
+                rating: Rating::default(),
+                source: None,
+                duplicate: false,
+            });
             continue;
+        }
+
+        let Some(last) = pending.last_mut() else {
+            return Err(Error::MissingField);
         };
 
-        if name != "image" {
+        if !matches!(name.as_deref(), Some("tags" | "rating" | "source")) {
             continue;
         }
 
-        let image = Image::new(&data, content_type);
+        let value = match text(field).await {
+            Ok(value) => value,
+            Err(Error::TooLarge) => return Err(Error::TooLarge),
+            Err(e) => {
+                last.upload = Err(e);
+                continue;
+            }
+        };
 
-        if db.image().get(&image.hash).await?.is_some() {
-            return Err(Error::ImageExists);
+        match name.as_deref() {
+            Some("tags") => match serde_json::from_str(&value) {
+                Ok(tags) => last.tags = tags,
+                Err(_) => last.upload = Err(Error::WrongType),
+            },
+            Some("rating") => match Rating::parse(&value) {
+                Some(rating) => last.rating = rating,
+                None => last.upload = Err(Error::WrongType),
+            },
+            Some("source") => last.source = Some(value),
+            _ => continue,
         }
+    }
 
-        upload(&image, data).await?;
-        let name = claims.sub;
+    if pending.is_empty() {
+        return Err(Error::MissingField);
+    }
 
-        let user = db.user().get(&name).await?.ok_or(Error::UserNotFound)?;
+    // stored once, the copies would race each other
+    let mut hashes = HashSet::new();
+    for p in &mut pending {
+        if let Ok(upload) = &p.upload {
+            p.duplicate = !hashes.insert(upload.hash.clone());
+        }
+    }
 
-        let image = db.image().create(&image).await?;
+    let db = &db;
+    let name = &claims.sub;
+
+    let results = stream::iter(pending)
+        .map(|p| async move {
+            let filename = p.filename;
+
+            let result = match p.upload {
+                Ok(upload) if p.duplicate => (Some(upload.hash.clone()), Err(Error::ImageExists)),
+                Ok(upload) => {
+                    let image = Image {
+                        rating: p.rating,
+                        source: p.source,
+                        ..Image::new(
+                            upload.hash.clone(),
+                            upload.sha256.clone(),
+                            upload.content_type.clone(),
+                        )
+                    };
+                    let hash = image.hash.clone();
+
+                    let stored = store(db, name, image, &upload, &p.tags).await;
+
+                    (Some(hash), stored.map(|_| ()))
+                }
+                Err(e) => (None, Err(e)),
+            };
+
+            match result {
+                (hash, Ok(())) => BatchResponse::new(filename, BatchStatus::Created, hash),
+                (hash, Err(Error::ImageExists)) => {
+                    BatchResponse::new(filename, BatchStatus::Duplicate, hash)
+                }
+                (hash, Err(e)) => BatchResponse::failed(filename, hash, e),
+            }
+        })
+        // results keep the order of the files
+        .buffered(config.concurrency)
+        .collect()
+        .await;
+
+    Ok(Json(results))
+}
 
-        if db.image().user(&image, &user).await.is_err() {
-            db.image().delete(image).await?;
-            return Err(Error::InvalidId);
-        }
+// shared by every upload route once the whole file is on disk
+pub async fn store(
+    db: &Database,
+    name: &String,
+    image: Image,
+    upload: &Upload,
+    tags: &[TagResponse],
+) -> Result<Image, Error> {
+    if db.image().get(&image.hash).await?.is_some() {
+        return Err(Error::ImageExists);
+    }
 
-        return Ok(image.hash);
+    // checked before anything is stored
+    let tags = resolve(db, tags).await?;
+    let user = db.user().get(name).await?.ok_or(Error::UserNotFound)?;
+
+    // hidden before the file is stored, so it is never public
+    if image.private {
+        cdn::visibility(&image.hash, true).await?;
     }
 
-    Err(Error::MissingField)
+    let media = cdn::upload(&image, upload).await?;
+    let image = Image {
+        media: Some(media),
+        ..image
+    };
+
+    db.image().create(&image, &user, &tags).await
+}
+
+// tags are given by name, implied tags are added
+async fn resolve(db: &Database, tags: &[TagResponse]) -> Result<Vec<Tag>, Error> {
+    let tagdb = db.tag();
+    let tags = try_join_all(tags.iter().map(|t| tagdb.get(&t.name, &t.category))).await?;
+
+    let tags = tags
+        .into_iter()
+        .collect::<Option<Vec<Tag>>>()
+        .ok_or(Error::TagNotFound)?;
+
+    // also removes duplicates from aliases resolving to the same tag
+    db.tag().implications(tags).await
 }
 
 #[derive(Deserialize)]
@@ -109,16 +289,30 @@ pub struct Delete {
 }
 
 pub async fn delete(
-    _: Claims,
+    claims: Claims,
     State(db): State<Database>,
     Json(query): Json<Delete>,
 ) -> Result<String, Error> {
-    let hash = query.hash;
+    claims.permit(Scope::Full)?;
+
+    let image = db
+        .image()
+        .get(&query.hash)
+        .await?
+        .ok_or(Error::ImageNotFound)?;
 
-    let image = db.image().get(&hash).await?.ok_or(Error::ImageNotFound)?;
+    let owner = db.user().from_image(&image).await?;
+    claims.owner_or(&owner.name, Role::Moderator)?;
 
+    // the cdn only knows the md5
+    let hash = image.hash.clone();
     db.image().delete(image).await?;
 
+    // the record is gone either way, leftovers are removed by the garbage collection
+    if cdn::delete(&hash).await.is_err() {
+        println!("Cdn: cannot delete {}", hash);
+    }
+
     Ok(hash)
 }
 
@@ -146,41 +340,69 @@ pub async fn post(
 #[derive(Deserialize)]
 pub struct Update {
     hash: String,
+    // left as they are when missing
     #[serde(default)]
-    tags: Vec<TagResponse>,
+    tags: Option<Vec<TagResponse>>,
+    #[serde(default)]
+    private: Option<bool>,
+    #[serde(default)]
+    rating: Option<Rating>,
 }
 
 pub async fn update(
-    _: Claims,
+    claims: Claims,
     State(db): State<Database>,
     Json(query): Json<Update>,
 ) -> Result<ImageResponse, Error> {
-    let Update { hash, tags } = query;
+    claims.permit(Scope::Full)?;
 
-    let image = db.image().get(&hash).await?.ok_or(Error::ImageNotFound)?;
+    let Update {
+        hash,
+        tags,
+        private,
+        rating,
+    } = query;
 
-    let old_tags = db.image().tagged(image.clone()).await?.tags;
+    let mut image = db.image().get(&hash).await?.ok_or(Error::ImageNotFound)?;
 
-    let tagdb = db.tag();
-    let tags = try_join_all(tags.iter().map(|t| tagdb.get(&t.name, &t.category))).await?;
+    if let Some(private) = private.filter(|p| *p != image.private) {
+        let owner = db.user().from_image(&image).await?;
+        claims.owner_or(&owner.name, Role::Moderator)?;
 
-    let new_tags = tags
-        .into_iter()
-        .collect::<Option<Vec<Tag>>>()
-        .ok_or(Error::DatabaseError)?;
+        cdn::visibility(&image.hash, private).await?;
+        image = db.image().set_private(image, private).await?;
+    }
+
+    if let Some(rating) = rating.filter(|r| *r != image.rating) {
+        image = db.image().set_rating(image, rating).await?;
+    }
+
+    if let Some(tags) = tags {
+        retag(&db, &image, &tags).await?;
+    }
+
+    let image = db.image().tagged(image).await?;
+
+    Ok(ImageResponse::new(image))
+}
+
+// replaces the tags of an image, implied tags included
+pub async fn retag(db: &Database, image: &Image, tags: &[TagResponse]) -> Result<(), Error> {
+    let old_tags = db.image().tagged(image.clone()).await?.tags;
+    let new_tags = resolve(db, tags).await?;
 
     let mut session = db.client.query(BeginStatement);
 
     for old in &old_tags {
         if !new_tags.contains(old) {
-            session = db.image().untag(&image, old, session)?;
+            session = db.image().untag(image, old, session)?;
             session = db.tag().update(old, -1, session)?;
         }
     }
 
     for new in &new_tags {
         if !old_tags.contains(new) {
-            session = db.image().tag(&image, new, session)?;
+            session = db.image().tag(image, new, session)?;
             session = db.tag().update(new, 1, session)?;
         }
     }
@@ -188,7 +410,5 @@ pub async fn update(
     let response = session.query(CommitStatement).await?;
     response.check()?;
 
-    let image = db.image().tagged(image).await?;
-
-    Ok(ImageResponse::new(image))
+    Ok(())
 }
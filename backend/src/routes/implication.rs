@@ -0,0 +1,70 @@
+use axum::{extract::State, Json};
+use serde::Deserialize;
+
+use crate::{
+    database::Database,
+    errors::Error,
+    jwt::Claims,
+    models::{apikey::Scope, tag::Tag, tagresponse::TagResponse, user::Role},
+};
+
+#[derive(Deserialize)]
+pub struct Target {
+    name: String,
+    category: String,
+}
+
+#[derive(Deserialize)]
+pub struct Implication {
+    tag: Target,
+    implies: Target,
+}
+
+async fn resolve(db: &Database, query: Implication) -> Result<(Tag, Tag), Error> {
+    let tag = db
+        .tag()
+        .get(&query.tag.name, &query.tag.category)
+        .await?
+        .ok_or(Error::TagNotFound)?;
+
+    let implies = db
+        .tag()
+        .get(&query.implies.name, &query.implies.category)
+        .await?
+        .ok_or(Error::TagNotFound)?;
+
+    Ok((tag, implies))
+}
+
+pub async fn create(
+    claims: Claims,
+    State(db): State<Database>,
+    Json(query): Json<Implication>,
+) -> Result<Json<Vec<TagResponse>>, Error> {
+    claims.permit(Scope::Full)?;
+    claims.require(Role::Janitor)?;
+
+    let (tag, implies) = resolve(&db, query).await?;
+
+    db.tag().imply(&tag, &implies).await?;
+
+    let tags = db.tag().implications(vec![implies]).await?;
+    let tags = tags.into_iter().map(TagResponse::new).collect();
+
+    Ok(Json(tags))
+}
+
+pub async fn delete(
+    claims: Claims,
+    State(db): State<Database>,
+    Json(query): Json<Implication>,
+) -> Result<(), Error> {
+    claims.permit(Scope::Full)?;
+    claims.require(Role::Janitor)?;
+
+    let (tag, implies) = resolve(&db, query).await?;
+
+    db.tag().unimply(&tag, &implies).await?;
+
+    Ok(())
+}
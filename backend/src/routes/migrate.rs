@@ -0,0 +1,91 @@
+use axum::extract::State;
+use futures::{stream, StreamExt};
+
+use crate::{
+    cdn, config,
+    database::Database,
+    errors::Error,
+    jwt::Claims,
+    models::{apikey::Scope, migrateresponse::MigrateResponse, user::Role},
+};
+
+// computes the sha256 of images uploaded before it existed, can be run again
+pub async fn sha256(claims: Claims, State(db): State<Database>) -> Result<MigrateResponse, Error> {
+    claims.permit(Scope::Full)?;
+    claims.require(Role::Admin)?;
+
+    let images = db.image().missing_sha256().await?;
+    let db = &db;
+
+    let results = stream::iter(images)
+        .map(|image| async move {
+            let result = match cdn::sha256(&image.hash).await {
+                Ok(sha256) => db.image().set_sha256(&image, sha256).await,
+                Err(e) => Err(e),
+            };
+
+            (image.hash, result)
+        })
+        .buffer_unordered(config::get().upload.concurrency)
+        .collect()
+        .await;
+
+    Ok(summary(results))
+}
+
+// reads the dimensions and size of images uploaded before they were kept
+pub async fn media(claims: Claims, State(db): State<Database>) -> Result<MigrateResponse, Error> {
+    claims.permit(Scope::Full)?;
+    claims.require(Role::Admin)?;
+
+    let images = db.image().missing_media().await?;
+    let db = &db;
+
+    let results = stream::iter(images)
+        .map(|image| async move {
+            let result = match cdn::media(&image.hash).await {
+                Ok(media) => db.image().set_media(&image, media).await,
+                Err(e) => Err(e),
+            };
+
+            (image.hash, result)
+        })
+        .buffer_unordered(config::get().upload.concurrency)
+        .collect()
+        .await;
+
+    Ok(summary(results))
+}
+
+// moves thumbnails of older layouts and applies preset changes, every image is done again
+pub async fn thumbs(claims: Claims, State(db): State<Database>) -> Result<MigrateResponse, Error> {
+    claims.permit(Scope::Full)?;
+    claims.require(Role::Admin)?;
+
+    let hashes = db.image().hashes().await?;
+
+    let results = stream::iter(hashes)
+        .map(|hash| async move {
+            let result = cdn::thumbnails(&hash).await;
+
+            (hash, result)
+        })
+        .buffer_unordered(config::get().upload.concurrency)
+        .collect()
+        .await;
+
+    Ok(summary(results))
+}
+
+fn summary(results: Vec<(String, Result<(), Error>)>) -> MigrateResponse {
+    let failed: Vec<String> = results
+        .iter()
+        .filter(|(_, r)| r.is_err())
+        .map(|(hash, _)| hash.clone())
+        .collect();
+
+    MigrateResponse {
+        updated: results.len() - failed.len(),
+        failed,
+    }
+}
@@ -2,17 +2,22 @@ use std::sync::Arc;
 
 use axum::{extract::State, Json};
 use axum_macros::debug_handler;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     database::Database,
     errors::Error,
+    filter::{Filter, Term},
     jwt::Claims,
-    models::{imageresponse::ImageResponse, tagresponse::TagResponse},
-    pattern::Pattern,
+    models::{
+        imageresponse::ImageResponse, searchresponse::SearchResponse, tag::Tag,
+        tagresponse::TagResponse,
+    },
+    pattern::{Pattern, Tagged},
+    query,
 };
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PatternTag {
     pub name: String,
     pub category: String,
@@ -21,36 +26,60 @@ pub struct PatternTag {
 #[derive(Debug, Deserialize)]
 pub struct SearchImage {
     #[serde(default)]
-    pattern: Option<Pattern<PatternTag>>,
+    pattern: Option<Pattern<Term<PatternTag>>>,
+    #[serde(default)]
+    query: Option<String>,
     #[serde(default)]
     previous: Option<String>,
 }
 
 #[debug_handler]
 pub async fn image(
-    _: Claims,
+    claims: Claims,
     State(db): State<Database>,
     Json(query): Json<SearchImage>,
-) -> Result<Json<Vec<ImageResponse>>, Error> {
-    let dbarc = Arc::new(&db);
-
-    let mut pattern = None;
-    if let Some(p) = query.pattern {
-        let closure = move |tag: PatternTag| {
-            let inside = Arc::clone(&dbarc);
-            async move {
-                inside
-                    .tag()
-                    .get(&tag.name, &tag.category)
-                    .await
-                    .map_err(|_| ())?
-                    .ok_or(())
-            }
-        };
-        let closure = Arc::new(closure);
-        let res = p.convert(closure).await.map_err(|_| Error::TagNotFound)?;
-        pattern = Some(res);
-    }
+) -> Result<SearchResponse, Error> {
+    let input = input(query.pattern, query.query)?;
+
+    let user = db
+        .user()
+        .get(&claims.sub)
+        .await?
+        .ok_or(Error::UserNotFound)?;
+
+    // the default ratings of the user, unless the search filters ratings itself
+    let rated = input.as_ref().map_or(false, |p| {
+        p.any(&|t| matches!(t, Term::Filter(Filter::Rating(_))))
+    });
+
+    let input = if rated || user.ratings.is_empty() {
+        input
+    } else {
+        let ratings = Pattern::Item(Term::Filter(Filter::Rating(user.ratings)));
+        Some(both(input, ratings))
+    };
+
+    let pattern = match input {
+        Some(p) => Some(resolve(&db, p).await?),
+        None => None,
+    };
+
+    // tags deleted since the blacklist was saved no longer hide anything
+    let blacklist = match user.blacklist {
+        Some(p) => lookup(&db, p).await?.prune(),
+        None => None,
+    };
+
+    // counted over the whole search, not only this page
+    let hidden = match &blacklist {
+        Some(b) => db.image().count(both(pattern.clone(), b.clone())).await?,
+        None => 0,
+    };
+
+    let pattern = match blacklist {
+        Some(b) => Some(both(pattern, Pattern::Tagged(Tagged::NOT(Box::new(b))))),
+        None => pattern,
+    };
 
     let previous = match query.previous {
         Some(hash) => Some(db.image().get(&hash).await?.ok_or(Error::ImageNotFound)?),
@@ -60,7 +89,71 @@ pub async fn image(
     let images = db.image().search(pattern, previous).await?;
     let images = images.into_iter().map(ImageResponse::new).collect();
 
-    Ok(Json(images))
+    Ok(SearchResponse { images, hidden })
+}
+
+// a pattern, or a query parsed into one
+pub fn input(
+    pattern: Option<Pattern<Term<PatternTag>>>,
+    query: Option<String>,
+) -> Result<Option<Pattern<Term<PatternTag>>>, Error> {
+    match (pattern, query) {
+        (Some(_), Some(_)) => Err(Error::ConflictingField),
+        (Some(p), None) => Ok(Some(p)),
+        (None, Some(q)) => query::parse(&q).map_err(Error::InvalidQuery),
+        (None, None) => Ok(None),
+    }
+}
+
+// tags are looked up by name and category, filters are kept as they are
+pub async fn resolve(
+    db: &Database,
+    pattern: Pattern<Term<PatternTag>>,
+) -> Result<Pattern<Term<Tag>>, Error> {
+    let missing = |term: Option<Term<Tag>>| async move { term.ok_or(()) };
+
+    lookup(db, pattern)
+        .await?
+        .convert(Arc::new(missing))
+        .await
+        .map_err(|_| Error::TagNotFound)
+}
+
+// None for the tags that do not exist
+async fn lookup(
+    db: &Database,
+    pattern: Pattern<Term<PatternTag>>,
+) -> Result<Pattern<Option<Term<Tag>>>, Error> {
+    let dbarc = Arc::new(db);
+
+    let closure = move |term: Term<PatternTag>| {
+        let inside = Arc::clone(&dbarc);
+        async move {
+            let tag = match term {
+                Term::Tag(tag) => tag,
+                Term::Filter(filter) => return Ok(Some(Term::Filter(filter))),
+            };
+
+            inside
+                .tag()
+                .get(&tag.name, &tag.category)
+                .await
+                .map(|t| t.map(Term::Tag))
+                .map_err(|_| ())
+        }
+    };
+
+    pattern
+        .convert(Arc::new(closure))
+        .await
+        .map_err(|_| Error::DatabaseError)
+}
+
+fn both<T>(pattern: Option<Pattern<T>>, other: Pattern<T>) -> Pattern<T> {
+    match pattern {
+        Some(p) => Pattern::Tagged(Tagged::AND(vec![p, other])),
+        None => other,
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -5,7 +5,7 @@ use crate::{
     database::Database,
     errors::Error,
     jwt::Claims,
-    models::{tag::Tag, tagresponse::TagResponse},
+    models::{apikey::Scope, tag::Tag, tagresponse::TagResponse, user::Role},
 };
 
 #[derive(Deserialize)]
@@ -20,6 +20,8 @@ pub async fn create(
     State(db): State<Database>,
     Json(query): Json<Create>,
 ) -> Result<TagResponse, Error> {
+    claims.permit(Scope::Full)?;
+
     let tag = Tag::new(query.name, query.category, query.description);
 
     if db.tag().get(&tag.name, &tag.category).await?.is_some() {
@@ -48,16 +50,21 @@ pub struct Delete {
 }
 
 pub async fn delete(
-    _: Claims,
+    claims: Claims,
     State(db): State<Database>,
     Json(query): Json<Delete>,
 ) -> Result<(), Error> {
+    claims.permit(Scope::Full)?;
+
     let tag = db
         .tag()
-        .get(&query.name, &query.category)
+        .find(&query.name, &query.category)
         .await?
         .ok_or(Error::TagNotFound)?;
 
+    let owner = db.user().from_tag(&tag).await?;
+    claims.owner_or(&owner.name, Role::Moderator)?;
+
     db.tag().delete(tag).await?;
 
     Ok(())
@@ -0,0 +1,348 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::SeekFrom,
+    sync::Mutex,
+};
+
+use axum::{
+    extract::{BodyStream, Path, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Duration, Utc};
+use futures::StreamExt;
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::{AsyncSeekExt, AsyncWriteExt},
+};
+
+use crate::{
+    config,
+    database::Database,
+    errors::Error,
+    jwt::Claims,
+    models::{
+        apikey::Scope,
+        image::{Image, Rating},
+        resumable::Resumable,
+    },
+    routes::image,
+    upload::{self, TempFile},
+};
+
+/* Resumable uploads, tus 1.0.0 (https://tus.io/protocols/resumable-upload)
+
+   OPTIONS /upload          capabilities
+   POST    /upload          Upload-Length, Upload-Metadata: filetype, private, rating
+   HEAD    /upload/:key     Upload-Offset reached so far
+   PATCH   /upload/:key     appends at Upload-Offset, the image is created once complete
+                            (409 while another PATCH of the same upload is running)
+
+   Unfinished uploads expire after upload.expire seconds.
+*/
+
+const TUS_VERSION: &str = "1.0.0";
+const TUS_EXTENSIONS: &str = "creation,expiration";
+const OFFSET_TYPE: &str = "application/offset+octet-stream";
+const LOCATION: &str = "/api/v1/upload";
+
+// read by browser clients, exposed through cors
+pub const HEADERS: [&str; 9] = [
+    "location",
+    "tus-resumable",
+    "tus-version",
+    "tus-extension",
+    "tus-max-size",
+    "upload-offset",
+    "upload-length",
+    "upload-expires",
+    "image-hash",
+];
+
+// keys with a PATCH in progress, a second one would write at the same offset
+static APPENDING: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+// held while appending, released even when the request fails
+struct Appending(String);
+
+impl Appending {
+    fn lock(key: &str) -> Result<Self, Error> {
+        if !APPENDING.lock().unwrap().insert(key.to_string()) {
+            return Err(Error::UploadOffset);
+        }
+
+        Ok(Self(key.to_string()))
+    }
+}
+
+impl Drop for Appending {
+    fn drop(&mut self) {
+        APPENDING.lock().unwrap().remove(&self.0);
+    }
+}
+
+fn tus_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert("tus-resumable", HeaderValue::from_static(TUS_VERSION));
+
+    headers
+}
+
+fn check_version(headers: &HeaderMap) -> Result<(), Error> {
+    match headers.get("tus-resumable") {
+        Some(version) if version == TUS_VERSION => Ok(()),
+        _ => Err(Error::TusVersion),
+    }
+}
+
+fn number(headers: &HeaderMap, name: &str) -> Result<u64, Error> {
+    headers
+        .get(name)
+        .ok_or(Error::MissingField)?
+        .to_str()
+        .map_err(|_| Error::WrongType)?
+        .parse()
+        .map_err(|_| Error::WrongType)
+}
+
+fn value(value: impl ToString) -> Result<HeaderValue, Error> {
+    HeaderValue::from_str(&value.to_string()).map_err(|_| Error::Serialize)
+}
+
+// http dates, as required by Upload-Expires
+fn expires(date: DateTime<Utc>) -> Result<HeaderValue, Error> {
+    value(date.format("%a, %d %b %Y %H:%M:%S GMT"))
+}
+
+// "key base64,key base64", values are optional
+fn metadata(headers: &HeaderMap) -> Result<HashMap<String, String>, Error> {
+    let Some(metadata) = headers.get("upload-metadata") else {
+        return Ok(HashMap::new());
+    };
+    let metadata = metadata.to_str().map_err(|_| Error::WrongType)?;
+
+    metadata
+        .split(',')
+        .map(|pair| {
+            let mut parts = pair.trim().splitn(2, ' ');
+            let key = parts.next().unwrap_or_default().to_string();
+
+            let value = match parts.next() {
+                Some(v) => STANDARD.decode(v).map_err(|_| Error::WrongType)?,
+                None => vec![],
+            };
+            let value = String::from_utf8(value).map_err(|_| Error::WrongType)?;
+
+            Ok((key, value))
+        })
+        .collect()
+}
+
+// the file size is the offset, it also counts interrupted requests
+async fn offset(resumable: &Resumable) -> Result<u64, Error> {
+    let metadata = fs::metadata(upload::temp_path(&resumable.key))
+        .await
+        .map_err(|_| Error::UploadNotFound)?;
+
+    Ok(metadata.len())
+}
+
+async fn owned(db: &Database, claims: &Claims, key: &str) -> Result<Resumable, Error> {
+    let resumable = db
+        .resumable()
+        .get(key)
+        .await?
+        .ok_or(Error::UploadNotFound)?;
+
+    if resumable.expire_at < Utc::now() {
+        return Err(Error::UploadExpired);
+    }
+
+    let user = db
+        .user()
+        .get(&claims.sub)
+        .await?
+        .ok_or(Error::UserNotFound)?;
+    if user.id.as_ref() != Some(&resumable.user) {
+        return Err(Error::UploadNotFound);
+    }
+
+    Ok(resumable)
+}
+
+// expired uploads are dropped whenever a new one starts
+async fn clean(db: &Database) -> Result<(), Error> {
+    for resumable in db.resumable().expired().await? {
+        drop(TempFile::new(&resumable.key));
+        db.resumable().delete(resumable).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn options() -> Result<Response, Error> {
+    let mut headers = tus_headers();
+    headers.insert("tus-version", HeaderValue::from_static(TUS_VERSION));
+    headers.insert("tus-extension", HeaderValue::from_static(TUS_EXTENSIONS));
+    headers.insert("tus-max-size", value(config::get().upload.max_size)?);
+
+    Ok((StatusCode::NO_CONTENT, headers).into_response())
+}
+
+pub async fn create(
+    claims: Claims,
+    State(db): State<Database>,
+    headers: HeaderMap,
+) -> Result<Response, Error> {
+    claims.permit(Scope::Upload)?;
+    check_version(&headers)?;
+
+    let config = &config::get().upload;
+
+    let length = number(&headers, "upload-length")?;
+    if length > config.max_size {
+        return Err(Error::TooLarge);
+    }
+
+    let metadata = metadata(&headers)?;
+    let content_type = metadata.get("filetype").ok_or(Error::MissingField)?;
+    let private = match metadata.get("private") {
+        Some(p) => p.parse().map_err(|_| Error::WrongType)?,
+        None => false,
+    };
+    let rating = match metadata.get("rating") {
+        Some(r) => Rating::parse(r).ok_or(Error::WrongType)?,
+        None => Rating::default(),
+    };
+
+    clean(&db).await?;
+
+    let user = db
+        .user()
+        .get(&claims.sub)
+        .await?
+        .ok_or(Error::UserNotFound)?;
+    let expire_at = Utc::now() + Duration::seconds(config.expire);
+
+    let resumable = db
+        .resumable()
+        .create(
+            &user,
+            length,
+            content_type.clone(),
+            private,
+            rating,
+            expire_at,
+        )
+        .await?;
+
+    File::create(upload::temp_path(&resumable.key))
+        .await
+        .map_err(|_| Error::Upload)?;
+
+    let mut response = tus_headers();
+    let location = format!("{}/{}", LOCATION, resumable.key);
+    response.insert(header::LOCATION, value(location)?);
+    response.insert("upload-expires", expires(expire_at)?);
+
+    Ok((StatusCode::CREATED, response).into_response())
+}
+
+pub async fn status(
+    claims: Claims,
+    State(db): State<Database>,
+    Path(key): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, Error> {
+    check_version(&headers)?;
+
+    let resumable = owned(&db, &claims, &key).await?;
+
+    let mut response = tus_headers();
+    response.insert("upload-offset", value(offset(&resumable).await?)?);
+    response.insert("upload-length", value(resumable.length)?);
+    response.insert("upload-expires", expires(resumable.expire_at)?);
+    response.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+
+    Ok((StatusCode::OK, response).into_response())
+}
+
+pub async fn append(
+    claims: Claims,
+    State(db): State<Database>,
+    Path(key): Path<String>,
+    headers: HeaderMap,
+    mut body: BodyStream,
+) -> Result<Response, Error> {
+    claims.permit(Scope::Upload)?;
+    check_version(&headers)?;
+
+    if headers.get(header::CONTENT_TYPE) != Some(&HeaderValue::from_static(OFFSET_TYPE)) {
+        return Err(Error::WrongType);
+    }
+
+    let resumable = owned(&db, &claims, &key).await?;
+    let _appending = Appending::lock(&resumable.key)?;
+
+    let start = number(&headers, "upload-offset")?;
+    if start != offset(&resumable).await? {
+        return Err(Error::UploadOffset);
+    }
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(upload::temp_path(&resumable.key))
+        .await
+        .map_err(|_| Error::Upload)?;
+    file.seek(SeekFrom::Start(start))
+        .await
+        .map_err(|_| Error::Upload)?;
+
+    let mut written = start;
+    while let Some(chunk) = body.next().await {
+        // a dropped connection keeps what was received, the client resumes from there
+        let Ok(chunk) = chunk else {
+            break;
+        };
+
+        if written + chunk.len() as u64 > resumable.length {
+            return Err(Error::TooLarge);
+        }
+
+        file.write_all(&chunk).await.map_err(|_| Error::Upload)?;
+        written += chunk.len() as u64;
+    }
+
+    file.flush().await.map_err(|_| Error::Upload)?;
+
+    let mut response = tus_headers();
+    response.insert("upload-offset", value(written)?);
+
+    if written == resumable.length {
+        let upload = upload::from_file(
+            TempFile::new(&resumable.key),
+            resumable.content_type.clone(),
+        )
+        .await?;
+
+        let image = Image {
+            private: resumable.private,
+            rating: resumable.rating,
+            ..Image::new(
+                upload.hash.clone(),
+                upload.sha256.clone(),
+                upload.content_type.clone(),
+            )
+        };
+
+        let stored = image::store(&db, &claims.sub, image, &upload, &[]).await;
+
+        // the file is gone either way, so is the upload
+        db.resumable().delete(resumable).await?;
+
+        response.insert("image-hash", value(stored?.hash)?);
+    }
+
+    Ok((StatusCode::NO_CONTENT, response).into_response())
+}
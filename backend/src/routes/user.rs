@@ -4,9 +4,26 @@ use serde::Deserialize;
 use crate::{
     database::Database,
     errors::Error,
+    filter::Term,
     jwt::{Claims, Token},
+    models::{
+        apikey::Scope,
+        image::Rating,
+        user::{Role, User},
+    },
+    pattern::Pattern,
+    routes::search::{self, PatternTag},
 };
 
+async fn issue(db: &Database, user: User) -> Result<Json<Token>, Error> {
+    let (refresh, refresh_token) = db.refresh().create(&user).await?;
+
+    let claims = Claims::new(user.name, user.role);
+    let token = claims.encode(refresh_token, refresh.expire_at.timestamp())?;
+
+    Ok(Json(token))
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Login {
     pub name: String,
@@ -25,10 +42,7 @@ pub async fn login(
 
     let user = db.user().authenticate(name, password).await?;
 
-    let claims = Claims::new(user.name);
-    let token = claims.encode()?;
-
-    Ok(Json(token))
+    issue(&db, user).await
 }
 
 #[derive(Debug, Deserialize)]
@@ -49,8 +63,123 @@ pub async fn signup(
 
     let user = db.user().create(name, password).await?;
 
-    let claims = Claims::new(user.name);
-    let token = claims.encode()?;
+    issue(&db, user).await
+}
 
-    Ok(Json(token))
+#[derive(Debug, Deserialize)]
+pub struct SetRole {
+    pub name: String,
+    pub role: Role,
+}
+
+pub async fn role(
+    claims: Claims,
+    State(db): State<Database>,
+    Json(query): Json<SetRole>,
+) -> Result<(), Error> {
+    claims.permit(Scope::Full)?;
+    claims.require(Role::Admin)?;
+
+    let user = db.user().get(&query.name).await?.ok_or(Error::UserNotFound)?;
+    db.user().set_role(&user, query.role).await?;
+
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetRatings {
+    pub ratings: Vec<Rating>,
+}
+
+// the ratings shown by default in searches
+pub async fn ratings(
+    claims: Claims,
+    State(db): State<Database>,
+    Json(query): Json<SetRatings>,
+) -> Result<(), Error> {
+    claims.permit(Scope::Full)?;
+
+    let user = db.user().get(&claims.sub).await?.ok_or(Error::UserNotFound)?;
+    db.user().set_ratings(&user, &query.ratings).await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetBlacklist {
+    #[serde(default)]
+    pattern: Option<Pattern<Term<PatternTag>>>,
+    #[serde(default)]
+    query: Option<String>,
+}
+
+// neither a pattern nor a query clears the blacklist
+pub async fn set_blacklist(
+    claims: Claims,
+    State(db): State<Database>,
+    Json(query): Json<SetBlacklist>,
+) -> Result<(), Error> {
+    claims.permit(Scope::Full)?;
+
+    let blacklist = search::input(query.pattern, query.query)?;
+
+    // its tags have to exist
+    if let Some(p) = &blacklist {
+        search::resolve(&db, p.clone()).await?;
+    }
+
+    let user = db.user().get(&claims.sub).await?.ok_or(Error::UserNotFound)?;
+    db.user().set_blacklist(&user, blacklist.as_ref()).await
+}
+
+pub async fn blacklist(
+    claims: Claims,
+    State(db): State<Database>,
+) -> Result<Json<Option<Pattern<Term<PatternTag>>>>, Error> {
+    let user = db.user().get(&claims.sub).await?.ok_or(Error::UserNotFound)?;
+
+    Ok(Json(user.blacklist))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Refresh {
+    pub refresh_token: String,
+}
+
+pub async fn refresh(
+    State(db): State<Database>,
+    Json(query): Json<Refresh>,
+) -> Result<Json<Token>, Error> {
+    // refresh tokens are single use
+    let refresh = db
+        .refresh()
+        .consume(&query.refresh_token)
+        .await?
+        .ok_or(Error::InvalidToken)?;
+
+    let user = db
+        .user()
+        .from_id(&refresh.user)
+        .await?
+        .ok_or(Error::UserNotFound)?;
+
+    issue(&db, user).await
+}
+
+pub async fn logout(
+    State(db): State<Database>,
+    Json(query): Json<Refresh>,
+) -> Result<(), Error> {
+    db.refresh()
+        .consume(&query.refresh_token)
+        .await?
+        .ok_or(Error::InvalidToken)?;
+
+    Ok(())
+}
+
+pub async fn logout_all(claims: Claims, State(db): State<Database>) -> Result<(), Error> {
+    claims.permit(Scope::Full)?;
+
+    let user = db.user().get(&claims.sub).await?.ok_or(Error::UserNotFound)?;
+
+    db.refresh().delete_user(&user).await
 }
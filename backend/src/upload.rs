@@ -0,0 +1,138 @@
+use std::path::PathBuf;
+
+use axum::extract::multipart::Field;
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncWriteExt},
+};
+
+use ring::digest::{Context, SHA256};
+
+use crate::{
+    config,
+    errors::Error,
+    jwt::{hex, random_token},
+};
+
+// removed once dropped, whether the upload succeeded or not
+pub struct TempFile {
+    pub path: PathBuf,
+}
+
+impl TempFile {
+    pub fn new(name: &str) -> Self {
+        Self {
+            path: temp_path(name),
+        }
+    }
+}
+
+pub fn temp_path(name: &str) -> PathBuf {
+    config::get().upload.temp_dir.join(name)
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+pub struct Upload {
+    pub file: TempFile,
+    pub hash: String,
+    pub sha256: String,
+    pub size: u64,
+    pub content_type: String,
+}
+
+// md5 names the files, sha256 is kept alongside as the stronger hash
+pub struct Hasher {
+    md5: md5::Context,
+    sha256: Context,
+}
+
+impl Hasher {
+    pub fn new() -> Self {
+        Self {
+            md5: md5::Context::new(),
+            sha256: Context::new(&SHA256),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.md5.consume(data);
+        self.sha256.update(data);
+    }
+
+    // (md5, sha256)
+    pub fn finish(self) -> (String, String) {
+        (
+            format!("{:x}", self.md5.compute()),
+            hex(self.sha256.finish().as_ref()),
+        )
+    }
+}
+
+// streams a multipart field to disk, hashing it on the way
+pub async fn receive(mut field: Field<'_>) -> Result<Upload, Error> {
+    let config = &config::get().upload;
+
+    let content_type = field.content_type().ok_or(Error::MissingField)?.to_string();
+
+    let file = TempFile::new(&random_token()?);
+    let mut out = File::create(&file.path).await.map_err(|_| Error::Upload)?;
+
+    let mut hasher = Hasher::new();
+    let mut size = 0;
+
+    while let Some(chunk) = field.chunk().await.map_err(|_| Error::Upload)? {
+        size += chunk.len() as u64;
+        if size > config.max_size {
+            return Err(Error::TooLarge);
+        }
+
+        hasher.update(&chunk);
+        out.write_all(&chunk).await.map_err(|_| Error::Upload)?;
+    }
+
+    out.flush().await.map_err(|_| Error::Upload)?;
+
+    let (hash, sha256) = hasher.finish();
+
+    Ok(Upload {
+        file,
+        hash,
+        sha256,
+        size,
+        content_type,
+    })
+}
+
+// for files written in several requests, hashed once complete
+pub async fn from_file(file: TempFile, content_type: String) -> Result<Upload, Error> {
+    let mut input = File::open(&file.path).await.map_err(|_| Error::Upload)?;
+
+    let mut hasher = Hasher::new();
+    let mut buf = vec![0; 64 * 1024];
+    let mut size = 0;
+
+    loop {
+        let read = input.read(&mut buf).await.map_err(|_| Error::Upload)?;
+        if read == 0 {
+            break;
+        }
+
+        size += read as u64;
+        hasher.update(&buf[..read]);
+    }
+
+    let (hash, sha256) = hasher.finish();
+
+    Ok(Upload {
+        file,
+        hash,
+        sha256,
+        size,
+        content_type,
+    })
+}
@@ -0,0 +1,227 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    body::{Body, HttpBody},
+    extract::State,
+    http::{header::CONTENT_TYPE, HeaderMap, Request},
+    middleware::Next,
+    response::Response,
+};
+use ring::{
+    digest::{digest, SHA256},
+    hmac,
+};
+use serde::Deserialize;
+
+use crate::{
+    config,
+    storage::{Kind, Storage},
+    AppState, Error,
+};
+
+/* Signed requests
+
+   Writes come from the backend only, every request carries
+
+   x-cdn-hash         file the request is about (empty for listing)
+   x-cdn-timestamp    unix seconds
+   x-cdn-nonce        random, unique to the request
+   x-cdn-digest       hex sha256 of the body, or of the file for uploads
+   x-cdn-signature    hex hmac-sha256 of
+                      "{method}\n{path}\n{hash}\n{timestamp}\n{nonce}\n{digest}"
+
+   A request older or newer than max_skew is rejected, and a nonce
+   is only accepted once while it is valid.
+   Bodies are buffered and checked here, multipart uploads are streamed
+   and checked against the digest by the handler.
+
+   Private files are read with ?expires=&signature=, the hex hmac-sha256
+   of "url\n{hash}\n{expires}", made by the backend for logged-in users.
+*/
+
+pub const HASH_HEADER: &str = "x-cdn-hash";
+pub const TIMESTAMP_HEADER: &str = "x-cdn-timestamp";
+pub const NONCE_HEADER: &str = "x-cdn-nonce";
+pub const DIGEST_HEADER: &str = "x-cdn-digest";
+pub const SIGNATURE_HEADER: &str = "x-cdn-signature";
+
+// signed bodies are small json, larger ones are not buffered
+const MAX_BODY: usize = 64 * 1024;
+
+// the hash the signature was made for, checked by the handlers
+#[derive(Debug, Clone)]
+pub struct Signed(pub String);
+
+// the signed sha256 of a streamed upload
+#[derive(Debug, Clone)]
+pub struct Digest(pub String);
+
+// nonces already used, with the time they stop being valid
+#[derive(Clone, Default)]
+pub struct Seen(Arc<Mutex<HashMap<String, i64>>>);
+
+impl Seen {
+    fn insert(&self, nonce: &str, expire: i64, now: i64) -> bool {
+        let mut seen = self.0.lock().unwrap();
+        seen.retain(|_, e| *e >= now);
+
+        seen.insert(nonce.to_string(), expire).is_none()
+    }
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Result<&'a str, Error> {
+    headers
+        .get(name)
+        .ok_or(Error::Unauthorized)?
+        .to_str()
+        .map_err(|_| Error::Unauthorized)
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn sign(message: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, config::get().secret.as_bytes());
+
+    hex(hmac::sign(&key, message.as_bytes()).as_ref())
+}
+
+pub fn sha256(data: &[u8]) -> String {
+    hex(digest(&SHA256, data).as_ref())
+}
+
+fn now() -> Result<i64, Error> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| Error::Server)?;
+
+    Ok(now.as_secs() as i64)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Access {
+    expires: Option<i64>,
+    signature: Option<String>,
+}
+
+impl Access {
+    // seconds left before the url expires, None when the file is public
+    pub async fn check(&self, storage: &dyn Storage, hash: &str) -> Result<Option<i64>, Error> {
+        if !storage.exists(Kind::Private, hash).await? {
+            return Ok(None);
+        }
+
+        let (Some(expires), Some(signature)) = (self.expires, &self.signature) else {
+            return Err(Error::Unauthorized);
+        };
+
+        let remaining = expires.checked_sub(now()?).ok_or(Error::Unauthorized)?;
+        if remaining < 0 {
+            return Err(Error::Unauthorized);
+        }
+
+        if !equals(signature, &sign(&format!("url\n{}\n{}", hash, expires))) {
+            return Err(Error::Unauthorized);
+        }
+
+        Ok(Some(remaining))
+    }
+}
+
+pub async fn verify(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Result<Response, Error> {
+    let (mut parts, body) = request.into_parts();
+    let headers = &parts.headers;
+
+    let hash = headers
+        .get(HASH_HEADER)
+        .map(|h| h.to_str().map_err(|_| Error::Unauthorized))
+        .transpose()?
+        .unwrap_or_default()
+        .to_string();
+
+    let timestamp: i64 = header(headers, TIMESTAMP_HEADER)?
+        .parse()
+        .map_err(|_| Error::Unauthorized)?;
+
+    let nonce = header(headers, NONCE_HEADER)?.to_string();
+    let digest = header(headers, DIGEST_HEADER)?.to_string();
+    let signature = header(headers, SIGNATURE_HEADER)?;
+
+    let streamed = headers
+        .get(CONTENT_TYPE)
+        .and_then(|c| c.to_str().ok())
+        .map_or(false, |c| c.starts_with("multipart/"));
+
+    let now = now()?;
+
+    let skew = config::get().max_skew;
+    if now.abs_diff(timestamp) > skew as u64 {
+        return Err(Error::Unauthorized);
+    }
+
+    let message = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        parts.method,
+        parts.uri.path(),
+        hash,
+        timestamp,
+        nonce,
+        digest
+    );
+    if !equals(signature, &sign(&message)) {
+        return Err(Error::Unauthorized);
+    }
+
+    if !state.seen.insert(&nonce, timestamp + skew, now) {
+        return Err(Error::Unauthorized);
+    }
+
+    let body = if streamed {
+        parts.extensions.insert(Digest(digest));
+        body
+    } else {
+        let data = read(body).await?;
+        if !equals(&digest, &sha256(&data)) {
+            return Err(Error::Unauthorized);
+        }
+
+        Body::from(data)
+    };
+
+    parts.extensions.insert(Signed(hash));
+
+    Ok(next.run(Request::from_parts(parts, body)).await)
+}
+
+async fn read(mut body: Body) -> Result<Vec<u8>, Error> {
+    let mut data = vec![];
+
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|_| Error::Read)?;
+        if data.len() + chunk.len() > MAX_BODY {
+            return Err(Error::TooLarge);
+        }
+
+        data.extend_from_slice(&chunk);
+    }
+
+    Ok(data)
+}
+
+// constant time, so the signature cannot be guessed byte by byte
+fn equals(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
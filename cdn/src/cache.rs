@@ -0,0 +1,169 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    sync::Mutex,
+};
+
+use crate::{
+    storage::{self, fs::FsStorage, Kind, Storage},
+    Error,
+};
+
+/* Resize cache
+
+   Resized images are kept on local disk up to max_size bytes,
+   the least recently used ones are removed first.
+   The order is rebuilt from the modification times at startup.
+*/
+
+// (variant, hash)
+type Key = (String, String);
+
+#[derive(Default)]
+struct Index {
+    tick: u64,
+    size: u64,
+    // key -> (last use, size)
+    entries: HashMap<Key, (u64, u64)>,
+    // last use -> key
+    order: BTreeMap<u64, Key>,
+}
+
+impl Index {
+    fn touch(&mut self, key: Key) -> bool {
+        let Some((used, _)) = self.entries.get_mut(&key) else {
+            return false;
+        };
+
+        self.order.remove(used);
+        self.tick += 1;
+        *used = self.tick;
+        self.order.insert(self.tick, key);
+
+        true
+    }
+
+    fn insert(&mut self, key: Key, size: u64) {
+        self.remove(&key);
+
+        self.tick += 1;
+        self.size += size;
+        self.entries.insert(key.clone(), (self.tick, size));
+        self.order.insert(self.tick, key);
+    }
+
+    fn remove(&mut self, key: &Key) {
+        if let Some((used, size)) = self.entries.remove(key) {
+            self.order.remove(&used);
+            self.size -= size;
+        }
+    }
+
+    // the least recently used entries, until the rest fits in max_size
+    fn evict(&mut self, max_size: u64) -> Vec<Key> {
+        let mut evicted = vec![];
+
+        while self.size > max_size {
+            let Some((_, key)) = self.order.pop_first() else {
+                break;
+            };
+
+            if let Some((_, size)) = self.entries.remove(&key) {
+                self.size -= size;
+            }
+            evicted.push(key);
+        }
+
+        evicted
+    }
+}
+
+pub struct Cache {
+    pub storage: FsStorage,
+    max_size: u64,
+    index: Mutex<Index>,
+}
+
+impl Cache {
+    pub async fn new(root: &Path, max_size: u64) -> Result<Self, Error> {
+        // nothing is private in the cache
+        let storage = FsStorage::new(root, root, root);
+
+        let mut files = vec![];
+        for variant in storage.variants().await? {
+            for (hash, modified) in storage.hashes(Kind::Thumb(&variant)).await? {
+                let size = storage.meta(Kind::Thumb(&variant), &hash).await?.size;
+                files.push((modified, (variant.clone(), hash), size));
+            }
+        }
+        files.sort();
+
+        let mut index = Index::default();
+        for (_, key, size) in files {
+            index.insert(key, size);
+        }
+
+        let cache = Self {
+            storage,
+            max_size,
+            index: Mutex::new(index),
+        };
+
+        // max_size may have been lowered since the last run
+        cache.evict().await?;
+
+        Ok(cache)
+    }
+
+    // counts as a use
+    pub fn contains(&self, variant: &str, hash: &str) -> bool {
+        let key = (variant.to_string(), hash.to_string());
+        self.index.lock().unwrap().touch(key)
+    }
+
+    pub async fn insert(
+        &self,
+        variant: &str,
+        hash: &str,
+        data: Vec<u8>,
+        content_type: &str,
+    ) -> Result<(), Error> {
+        let size = data.len() as u64;
+        self.storage
+            .write(Kind::Thumb(variant), hash, data, content_type)
+            .await?;
+
+        let key = (variant.to_string(), hash.to_string());
+        self.index.lock().unwrap().insert(key, size);
+
+        self.evict().await
+    }
+
+    // every resize of the hash
+    pub async fn purge(&self, hash: &str) -> Result<(), Error> {
+        storage::purge(&self.storage, hash).await?;
+
+        let mut index = self.index.lock().unwrap();
+        let keys: Vec<Key> = index
+            .entries
+            .keys()
+            .filter(|(_, h)| h == hash)
+            .cloned()
+            .collect();
+        for key in keys {
+            index.remove(&key);
+        }
+
+        Ok(())
+    }
+
+    async fn evict(&self) -> Result<(), Error> {
+        let evicted = self.index.lock().unwrap().evict(self.max_size);
+
+        for (variant, hash) in evicted {
+            self.storage.delete(Kind::Thumb(&variant), &hash).await?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,319 @@
+use std::{
+    collections::BTreeMap, env, fs, net::SocketAddr, path::PathBuf, str::FromStr, sync::OnceLock,
+};
+
+use serde::Deserialize;
+
+use crate::{thumbnail::Format, Error};
+
+/* Configuration
+
+   Read from the toml file at $CONFIG (default: config.toml, optional),
+   then every field can be overridden by its environment variable.
+
+   bind           BIND_ADDR
+   secret         API_SECRET      (shared with the backend to sign writes)
+   max_skew       MAX_SKEW        (seconds a signed request stays valid)
+   storage        STORAGE         (fs or s3)
+   assets         ASSETS          (fs: originals)
+   thumbs         THUMBS          (fs: thumbnails)
+   private        PRIVATE         (fs: markers of the private files)
+   cache          CACHE           (on-demand resizes, always local)
+   cache_max_size CACHE_MAX_SIZE  (bytes, least recently used resizes are removed past it)
+   temp_dir       TEMP_DIR        (uploads are written there first)
+   max_size       MAX_SIZE        (largest upload, in bytes)
+   max_resize     MAX_RESIZE      (largest width/height for /resize)
+   resize_steps                   (the other widths/heights /resize accepts)
+   resize_formats                 (picked from the Accept header, first one is the default)
+   default_preset DEFAULT_PRESET  (served by /thumb/:id)
+   [presets.<name>]
+   width, height                  (generated at upload)
+   formats                        (jpeg, png, webp, avif; first one is the default)
+   quality                        (jpeg and avif, 1 to 100)
+   [s3]
+   bucket         S3_BUCKET
+   region         S3_REGION
+   endpoint       S3_ENDPOINT
+   access_key     S3_ACCESS_KEY
+   secret_key     S3_SECRET_KEY
+   path_style     S3_PATH_STYLE
+*/
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub bind: SocketAddr,
+    pub secret: String,
+    pub max_skew: i64,
+    pub storage: Backend,
+    pub assets: PathBuf,
+    pub thumbs: PathBuf,
+    pub private: PathBuf,
+    pub cache: PathBuf,
+    pub cache_max_size: u64,
+    pub temp_dir: PathBuf,
+    pub max_size: u64,
+    pub max_resize: u32,
+    pub resize_steps: Vec<u32>,
+    pub resize_formats: Vec<Format>,
+    pub default_preset: String,
+    pub presets: BTreeMap<String, Preset>,
+    pub s3: S3Config,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Preset {
+    pub width: u32,
+    pub height: u32,
+    #[serde(default = "Preset::default_formats")]
+    pub formats: Vec<Format>,
+    #[serde(default = "Preset::default_quality")]
+    pub quality: u8,
+}
+
+impl Preset {
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            formats: Self::default_formats(),
+            quality: Self::default_quality(),
+        }
+    }
+
+    fn default_formats() -> Vec<Format> {
+        vec![Format::Jpeg]
+    }
+
+    fn default_quality() -> u8 {
+        85
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    Fs,
+    S3,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
+pub struct S3Config {
+    pub bucket: String,
+    pub region: String,
+    pub endpoint: String,
+    pub access_key: String,
+    pub secret_key: String,
+    pub path_style: bool,
+}
+
+impl FromStr for Backend {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fs" => Ok(Backend::Fs),
+            "s3" => Ok(Backend::S3),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind: SocketAddr::from(([127, 0, 0, 1], 4000)),
+            secret: String::new(),
+            max_skew: 300,
+            storage: Backend::Fs,
+            assets: PathBuf::from("./assets"),
+            thumbs: PathBuf::from("./thumbs"),
+            private: PathBuf::from("./private"),
+            cache: PathBuf::from("./cache"),
+            cache_max_size: 1024 * 1024 * 1024,
+            temp_dir: env::temp_dir().join("cdn"),
+            max_size: 50 * 1024 * 1024,
+            max_resize: 4096,
+            resize_steps: vec![64, 128, 256, 512, 1024, 2048],
+            resize_formats: vec![Format::Jpeg, Format::Webp],
+            default_preset: "medium".to_string(),
+            presets: BTreeMap::from([
+                ("small".to_string(), Preset::new(150, 150)),
+                ("medium".to_string(), Preset::new(400, 400)),
+                ("sample".to_string(), Preset::new(850, 850)),
+            ]),
+            s3: S3Config::default(),
+        }
+    }
+}
+
+fn invalid(reason: &str) -> Error {
+    println!("Config: {}", reason);
+    Error::Config
+}
+
+fn set<T: FromStr>(value: &mut T, key: &str) -> Result<(), Error> {
+    if let Ok(v) = env::var(key) {
+        *value = v
+            .parse()
+            .map_err(|_| invalid(&format!("cannot parse {}", key)))?;
+    }
+
+    Ok(())
+}
+
+// nested or shared directories would mix originals, thumbnails and resizes,
+// deleting one kind could then delete another
+fn distinct(dirs: &[&PathBuf]) -> Result<(), Error> {
+    let dirs = dirs
+        .iter()
+        .map(|dir| {
+            fs::canonicalize(dir).map_err(|_| invalid(&format!("cannot resolve {}", dir.display())))
+        })
+        .collect::<Result<Vec<PathBuf>, Error>>()?;
+
+    for (i, a) in dirs.iter().enumerate() {
+        if let Some(b) = dirs[i + 1..]
+            .iter()
+            .find(|b| a.starts_with(b) || b.starts_with(a))
+        {
+            return Err(invalid(&format!(
+                "{} and {} overlap",
+                a.display(),
+                b.display()
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+impl Config {
+    pub fn load() -> Result<Self, Error> {
+        let path = env::var("CONFIG").unwrap_or_else(|_| "config.toml".to_string());
+
+        let mut config = match fs::read_to_string(&path) {
+            Ok(data) => toml::from_str(&data).map_err(|e| invalid(&e.to_string()))?,
+            Err(_) => Config::default(),
+        };
+
+        set(&mut config.bind, "BIND_ADDR")?;
+        set(&mut config.secret, "API_SECRET")?;
+        set(&mut config.max_skew, "MAX_SKEW")?;
+        set(&mut config.storage, "STORAGE")?;
+        set(&mut config.assets, "ASSETS")?;
+        set(&mut config.thumbs, "THUMBS")?;
+        set(&mut config.private, "PRIVATE")?;
+        set(&mut config.cache, "CACHE")?;
+        set(&mut config.cache_max_size, "CACHE_MAX_SIZE")?;
+        set(&mut config.temp_dir, "TEMP_DIR")?;
+        set(&mut config.max_size, "MAX_SIZE")?;
+        set(&mut config.max_resize, "MAX_RESIZE")?;
+        set(&mut config.default_preset, "DEFAULT_PRESET")?;
+        set(&mut config.s3.bucket, "S3_BUCKET")?;
+        set(&mut config.s3.region, "S3_REGION")?;
+        set(&mut config.s3.endpoint, "S3_ENDPOINT")?;
+        set(&mut config.s3.access_key, "S3_ACCESS_KEY")?;
+        set(&mut config.s3.secret_key, "S3_SECRET_KEY")?;
+        set(&mut config.s3.path_style, "S3_PATH_STYLE")?;
+
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        // preset names end up in paths and urls
+        let valid = |name: &String| {
+            !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        };
+
+        if self.secret.is_empty() {
+            return Err(invalid("api secret is missing"));
+        }
+        if self.max_skew <= 0 {
+            return Err(invalid("max_skew must be positive"));
+        }
+        if let Some(name) = self.presets.keys().find(|name| !valid(name)) {
+            return Err(invalid(&format!("invalid preset name {}", name)));
+        }
+        if !self.presets.contains_key(&self.default_preset) {
+            return Err(invalid("default_preset is not a preset"));
+        }
+        if self.presets.values().any(|p| p.width == 0 || p.height == 0) {
+            return Err(invalid("preset sizes must be positive"));
+        }
+        if self.presets.values().any(|p| p.formats.is_empty()) {
+            return Err(invalid("presets need at least one format"));
+        }
+        if self
+            .presets
+            .values()
+            .any(|p| p.quality == 0 || p.quality > 100)
+        {
+            return Err(invalid("preset quality must be between 1 and 100"));
+        }
+        if self.max_size == 0 {
+            return Err(invalid("max_size must be positive"));
+        }
+        if self.max_resize == 0 {
+            return Err(invalid("max_resize must be positive"));
+        }
+        if self
+            .resize_steps
+            .iter()
+            .any(|s| *s == 0 || *s > self.max_resize)
+        {
+            return Err(invalid("resize_steps must be between 1 and max_resize"));
+        }
+        if self.resize_formats.is_empty() {
+            return Err(invalid("resize_formats needs at least one format"));
+        }
+        if self.cache_max_size == 0 {
+            return Err(invalid("cache_max_size must be positive"));
+        }
+
+        for dir in [&self.cache, &self.temp_dir] {
+            fs::create_dir_all(dir)
+                .map_err(|_| invalid(&format!("cannot create {}", dir.display())))?;
+        }
+
+        match self.storage {
+            Backend::Fs => {
+                for dir in [&self.assets, &self.thumbs, &self.private] {
+                    fs::create_dir_all(dir)
+                        .map_err(|_| invalid(&format!("cannot create {}", dir.display())))?;
+                }
+
+                distinct(&[&self.assets, &self.thumbs, &self.private, &self.cache])?;
+            }
+            Backend::S3 => {
+                let s3 = &self.s3;
+                if s3.bucket.is_empty() || s3.endpoint.is_empty() {
+                    return Err(invalid("s3 bucket and endpoint are required"));
+                }
+                if s3.access_key.is_empty() || s3.secret_key.is_empty() {
+                    return Err(invalid("s3 credentials are missing"));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub fn init() -> Result<&'static Config, Error> {
+    let config = Config::load()?;
+
+    Ok(CONFIG.get_or_init(|| config))
+}
+
+pub fn get() -> &'static Config {
+    CONFIG.get().expect("Config not initialized")
+}
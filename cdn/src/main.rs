@@ -1,24 +1,39 @@
+mod auth;
+mod cache;
+mod config;
+mod media;
+mod serve;
+mod storage;
+mod thumbnail;
+mod upload;
+
 use std::{
-    env,
-    io::{BufReader, Cursor},
-    net::SocketAddr,
-    path::PathBuf, fs,
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
 };
 
+use auth::{Access, Digest, Seen, Signed};
 use axum::{
-    extract::{Multipart, Path},
-    http::{header, HeaderMap, StatusCode},
+    extract::{DefaultBodyLimit, Multipart, Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware,
     response::{IntoResponse, Response},
-    routing::{get, post},
-    Json, Router, Server,
+    routing::{delete, get, post},
+    Extension, Json, Router, Server,
 };
+use cache::Cache;
 use dotenv::dotenv;
+use media::Media;
+use serde::Deserialize;
 use serde_json::json;
-use thumbnailer::{create_thumbnails, Thumbnail, ThumbnailSize};
+use serve::serve;
+use storage::{is_hash, sniff, Kind, Store};
+use thumbnail::Fit;
 
 #[derive(Debug)]
 enum Error {
     Server,
+    Config,
     WrongFilename,
     WrongField,
     WrongMime,
@@ -26,12 +41,16 @@ enum Error {
     Read,
     Exists,
     NotFound,
+    WrongSize,
+    TooLarge,
+    Unauthorized,
 }
 
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
         let (status, message) = match self {
             Error::Server => (StatusCode::INTERNAL_SERVER_ERROR, "Server Start"),
+            Error::Config => (StatusCode::INTERNAL_SERVER_ERROR, "Configuration"),
             Error::WrongFilename => (StatusCode::BAD_REQUEST, "Wrong Filename"),
             Error::WrongField => (StatusCode::BAD_REQUEST, "Wrong Field"),
             Error::WrongMime => (StatusCode::BAD_REQUEST, "Wrong Mime"),
@@ -39,6 +58,9 @@ impl IntoResponse for Error {
             Error::Read => (StatusCode::BAD_REQUEST, "Read File"),
             Error::Exists => (StatusCode::BAD_REQUEST, "File already exists"),
             Error::NotFound => (StatusCode::BAD_REQUEST, "File not found"),
+            Error::WrongSize => (StatusCode::BAD_REQUEST, "Wrong Size"),
+            Error::TooLarge => (StatusCode::PAYLOAD_TOO_LARGE, "File too large"),
+            Error::Unauthorized => (StatusCode::UNAUTHORIZED, "Unauthorized"),
         };
 
         let body = Json(json!({
@@ -49,149 +71,406 @@ impl IntoResponse for Error {
     }
 }
 
-fn split_string(s: String) -> (String, String, String, String) {
-    let (x, rest) = s.split_at(2);
-    let (y, rest) = rest.split_at(2);
-    let (z, rest) = rest.split_at(2);
+#[derive(Clone)]
+struct AppState {
+    storage: Store,
+    cache: Arc<Cache>,
+    seen: Seen,
+}
+
+async fn add(
+    State(state): State<AppState>,
+    Extension(Signed(hash)): Extension<Signed>,
+    Extension(Digest(digest)): Extension<Digest>,
+    mut multipart: Multipart,
+) -> Result<Json<Media>, Error> {
+    let storage = &state.storage;
 
-    (
-        x.to_string(),
-        y.to_string(),
-        z.to_string(),
-        rest.to_string(),
+    while let Ok(Some(field)) = multipart.next_field().await {
+        let name = field.name().ok_or(Error::WrongField)?;
+
+        if name != "file" {
+            return Err(Error::WrongFilename);
+        }
+
+        let filename = field.file_name().ok_or(Error::WrongField)?.to_string();
+
+        if !is_hash(&filename) {
+            return Err(Error::WrongFilename);
+        }
+
+        // the signature only covers the announced hash
+        if filename != hash {
+            return Err(Error::Unauthorized);
+        }
+
+        let content_type = field.content_type().ok_or(Error::WrongField)?;
+        let content_type: mime::Mime = content_type.parse().map_err(|_| Error::WrongMime)?;
+
+        let received = upload::receive(field, &filename).await?;
+
+        // the signature covers the content through its sha256
+        if received.sha256 != digest {
+            return Err(Error::Unauthorized);
+        }
+
+        let essence = content_type.essence_str().to_string();
+
+        // the declared type is what gets served, so it has to match the content
+        if sniff(&received.head) != essence {
+            return Err(Error::WrongMime);
+        }
+
+        if storage.exists(Kind::Asset, &filename).await? {
+            return Err(Error::Exists);
+        }
+
+        storage
+            .write_file(Kind::Asset, &filename, &received.file.path, &essence)
+            .await?;
+
+        let path = received.file.path.clone();
+        let size = received.size;
+        let media = tokio::task::spawn_blocking(move || media::extract(&path, &essence, size))
+            .await
+            .map_err(|_| Error::Read)?;
+
+        let path = received.file.path.clone();
+        let thumbs = tokio::task::spawn_blocking(move || thumbnail::presets(&path, content_type))
+            .await
+            .map_err(|_| Error::Write)??;
+
+        for (variant, mime, thumb) in thumbs {
+            storage
+                .write(Kind::Thumb(&variant), &filename, thumb, mime)
+                .await?;
+        }
+
+        return Ok(Json(media));
+    }
+
+    Err(Error::WrongField)
+}
+
+// signed urls expire, so private files are kept out of shared caches
+fn restrict(mut response: Response, remaining: Option<i64>) -> Response {
+    if let Some(remaining) = remaining {
+        let value = format!("private, max-age={}", remaining);
+
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            response.headers_mut().insert(header::CACHE_CONTROL, value);
+        }
+    }
+
+    response
+}
+
+async fn thumb(
+    State(state): State<AppState>,
+    Path(filename): Path<String>,
+    Query(access): Query<Access>,
+    headers: HeaderMap,
+) -> Result<Response, Error> {
+    let preset = &config::get().default_preset;
+
+    preset_thumb(
+        State(state),
+        Path((preset.clone(), filename)),
+        Query(access),
+        headers,
     )
+    .await
 }
 
-fn generate_path(root: &str, s: String) -> (PathBuf, String) {
-    let (a, b, c, name) = split_string(s);
+async fn preset_thumb(
+    State(state): State<AppState>,
+    Path((preset, filename)): Path<(String, String)>,
+    Query(access): Query<Access>,
+    headers: HeaderMap,
+) -> Result<Response, Error> {
+    if !is_hash(&filename) {
+        return Err(Error::WrongFilename);
+    }
+
+    let remaining = access.check(&*state.storage, &filename).await?;
 
-    let assets = match env::var(root) {
-        Ok(p) => p,
-        Err(_) => "./".to_string(),
+    let Some(config) = config::get().presets.get(&preset) else {
+        return Err(Error::NotFound);
     };
 
-    let dir = PathBuf::from(assets).join(a).join(b).join(c);
+    let format = thumbnail::negotiate(&config.formats, &headers);
+    let variant = thumbnail::variant(&preset, format);
+
+    let storage = &*state.storage;
 
-    (dir, name)
+    // thumbnails of older layouts are served until they are regenerated
+    let mut response = Err(Error::NotFound);
+    for kind in [
+        Kind::Thumb(&variant),
+        Kind::Thumb(&preset),
+        Kind::LegacyThumb,
+    ] {
+        response = serve(storage, kind, &filename, &headers).await;
+
+        if !matches!(response, Err(Error::NotFound)) {
+            break;
+        }
+    }
+
+    let mut response = response?;
+    response
+        .headers_mut()
+        .insert(header::VARY, HeaderValue::from_static("accept"));
+
+    Ok(restrict(response, remaining))
 }
 
-fn save(root: &str, filename: String, data: &Vec<u8>) -> Result<(), Error> {
-    let (dir, name) = generate_path(root, filename);
-    let path = dir.join(name);
+// rebuilds the thumbnails of a file with the current presets,
+// and removes the ones of older layouts or removed presets
+async fn regenerate(
+    State(state): State<AppState>,
+    Extension(Signed(hash)): Extension<Signed>,
+    Path(filename): Path<String>,
+) -> Result<(), Error> {
+    if !is_hash(&filename) {
+        return Err(Error::WrongFilename);
+    }
 
-    if path.exists() {
-        return Err(Error::Exists);
+    if filename != hash {
+        return Err(Error::Unauthorized);
     }
 
-    fs::create_dir_all(dir).map_err(|_| Error::Write)?;
+    let storage = &*state.storage;
 
-    fs::write(path, data).map_err(|_| Error::Write)?;
+    let content_type: mime::Mime = storage
+        .meta(Kind::Asset, &filename)
+        .await?
+        .content_type
+        .parse()
+        .map_err(|_| Error::WrongMime)?;
 
-    Ok(())
-}
+    let file = upload::download(storage, Kind::Asset, &filename).await?;
+    let path = file.path.clone();
+    let thumbs = tokio::task::spawn_blocking(move || thumbnail::presets(&path, content_type))
+        .await
+        .map_err(|_| Error::Write)??;
+
+    let mut current = BTreeSet::new();
+    for (variant, mime, thumb) in thumbs {
+        storage
+            .write(Kind::Thumb(&variant), &filename, thumb, mime)
+            .await?;
+        current.insert(variant);
+    }
 
-fn save_thumb(filename: String, thumb: Thumbnail) -> Result<(), Error> {
-    let mut buf = Cursor::new(Vec::new());
-    thumb.write_jpeg(&mut buf, 85).map_err(|_| Error::Write)?;
-    let data = buf.into_inner();
+    storage::prune(storage, &filename, &current).await?;
 
-    save("THUMBS", filename, &data)
+    storage.delete(Kind::LegacyThumb, &filename).await
 }
 
-async fn add(mut multipart: Multipart) -> Result<(), Error> {
-    while let Ok(Some(field)) = multipart.next_field().await {
-        let name = field.name().ok_or(Error::WrongField)?;
-
-        if name != "file" {
-            return Err(Error::WrongFilename);
-        }
+#[derive(Deserialize)]
+struct Resize {
+    w: Option<u32>,
+    h: Option<u32>,
+    #[serde(default)]
+    fit: Fit,
+}
 
-        let filename = field.file_name().ok_or(Error::WrongField)?.to_string();
+async fn resize(
+    State(state): State<AppState>,
+    Path(filename): Path<String>,
+    Query(query): Query<Resize>,
+    Query(access): Query<Access>,
+    headers: HeaderMap,
+) -> Result<Response, Error> {
+    if !is_hash(&filename) {
+        return Err(Error::WrongFilename);
+    }
 
-        if filename.len() != 32 {
-            return Err(Error::WrongFilename);
-        }
+    let remaining = access.check(&*state.storage, &filename).await?;
 
-        let content_type = field.content_type().ok_or(Error::WrongField)?;
-        let content_type: mime::Mime = content_type.parse().map_err(|_| Error::WrongMime)?;
+    let config = config::get();
+    let max = config.max_resize;
+    let (width, height) = match (query.w, query.h, query.fit) {
+        (Some(w), Some(h), _) => (w, h),
+        (Some(w), None, Fit::Contain) => (w, max),
+        (None, Some(h), Fit::Contain) => (max, h),
+        _ => return Err(Error::WrongSize),
+    };
 
-        let data = field.bytes().await.map_err(|_| Error::WrongField)?;
+    // a fixed set of sizes, so the cache does not grow with every request
+    let allowed = |size: u32| size == max || config.resize_steps.contains(&size);
+    if !allowed(width) || !allowed(height) {
+        return Err(Error::WrongSize);
+    }
 
-        save("ASSETS", filename.clone(), &data.to_vec())?;
+    let format = thumbnail::negotiate(&config.resize_formats, &headers);
+    let variant = format!("{}x{}-{:?}-{}", width, height, query.fit, format.name()).to_lowercase();
 
-        let cursor = Cursor::new(data);
-        let reader = BufReader::new(cursor);
-        let thumb = create_thumbnails(reader, content_type, [ThumbnailSize::Medium])
-            .map_err(|_| Error::Write)?
-            .pop()
-            .ok_or(Error::Write)?;
+    if !state.cache.contains(&variant, &filename) {
+        let file = upload::download(&*state.storage, Kind::Asset, &filename).await?;
 
-        save_thumb(filename, thumb)?;
+        let fit = query.fit;
+        let (mime, resized) = tokio::task::spawn_blocking(move || {
+            thumbnail::resize(&file.path, width, height, fit, format)
+        })
+        .await
+        .map_err(|_| Error::Write)??;
 
-        return Ok(());
+        state
+            .cache
+            .insert(&variant, &filename, resized, mime)
+            .await?;
     }
 
-    Err(Error::WrongField)
+    let mut response = serve(
+        &state.cache.storage,
+        Kind::Thumb(&variant),
+        &filename,
+        &headers,
+    )
+    .await?;
+    response
+        .headers_mut()
+        .insert(header::VARY, HeaderValue::from_static("accept"));
+
+    Ok(restrict(response, remaining))
 }
 
-async fn thumb(Path(filename): Path<String>) -> Result<Response, Error> {
-    if filename.len() != 32 {
+async fn image(
+    State(state): State<AppState>,
+    Path(filename): Path<String>,
+    Query(access): Query<Access>,
+    headers: HeaderMap,
+) -> Result<Response, Error> {
+    if !is_hash(&filename) {
         return Err(Error::WrongFilename);
     }
 
-    let (dir, name) = generate_path("THUMBS", filename);
-    let path = dir.join(name);
+    let remaining = access.check(&*state.storage, &filename).await?;
+    let response = serve(&*state.storage, Kind::Asset, &filename, &headers).await?;
 
-    if !path.exists() {
-        return Err(Error::NotFound);
+    Ok(restrict(response, remaining))
+}
+
+// for files stored before their metadata was kept
+async fn meta(
+    State(state): State<AppState>,
+    Path(filename): Path<String>,
+    Query(access): Query<Access>,
+) -> Result<Json<Media>, Error> {
+    if !is_hash(&filename) {
+        return Err(Error::WrongFilename);
     }
 
-    let data = fs::read(path).map_err(|_| Error::Read)?;
+    access.check(&*state.storage, &filename).await?;
+
+    let meta = state.storage.meta(Kind::Asset, &filename).await?;
+    let file = upload::download(&*state.storage, Kind::Asset, &filename).await?;
 
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        header::CONTENT_TYPE,
-        "image/png".parse().expect("cannot parse string"),
-    );
+    let media = tokio::task::spawn_blocking(move || {
+        media::extract(&file.path, &meta.content_type, meta.size)
+    })
+    .await
+    .map_err(|_| Error::Read)?;
 
-    Ok((headers, data).into_response())
+    Ok(Json(media))
 }
 
-async fn image(Path(filename): Path<String>) -> Result<Response, Error> {
-    if filename.len() != 32 {
+#[derive(Deserialize)]
+struct Visibility {
+    private: bool,
+}
+
+// the marker can be set before the upload, so a private file is never public
+async fn visibility(
+    State(state): State<AppState>,
+    Extension(Signed(hash)): Extension<Signed>,
+    Path(filename): Path<String>,
+    Json(query): Json<Visibility>,
+) -> Result<(), Error> {
+    if !is_hash(&filename) {
         return Err(Error::WrongFilename);
     }
 
-    let (dir, name) = generate_path("ASSETS", filename);
-    let path = dir.join(name);
+    if filename != hash {
+        return Err(Error::Unauthorized);
+    }
+
+    match query.private {
+        true => {
+            state
+                .storage
+                .write(Kind::Private, &filename, vec![], "text/plain")
+                .await
+        }
+        false => state.storage.delete(Kind::Private, &filename).await,
+    }
+}
 
-    if !path.exists() {
-        return Err(Error::NotFound);
+async fn remove(
+    State(state): State<AppState>,
+    Extension(Signed(hash)): Extension<Signed>,
+    Path(filename): Path<String>,
+) -> Result<(), Error> {
+    if !is_hash(&filename) {
+        return Err(Error::WrongFilename);
     }
 
-    let data = fs::read(path).map_err(|_| Error::Read)?;
+    if filename != hash {
+        return Err(Error::Unauthorized);
+    }
 
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        header::CONTENT_TYPE,
-        "image/jpeg".parse().expect("cannot parse string"),
-    );
+    // also clears thumbnails left without an original
+    storage::purge(&*state.storage, &filename).await?;
+    state.cache.purge(&filename).await
+}
 
-    Ok((headers, data).into_response())
+// used by the backend garbage collection to find orphaned files,
+// with the unix time they were last written
+async fn list(State(state): State<AppState>) -> Result<Json<BTreeMap<String, i64>>, Error> {
+    Ok(Json(storage::stored(&*state.storage).await?))
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     dotenv().ok();
+    let config = config::init()?;
+    let state = AppState {
+        storage: storage::from_config(config)?,
+        cache: Arc::new(Cache::new(&config.cache, config.cache_max_size).await?),
+        seen: Seen::default(),
+    };
 
-    let app = Router::new()
-        .route("/", post(add))
-        .route("/:id", get(image))
-        .route("/thumb/:id", get(thumb));
-
-    let addr = SocketAddr::from(([127, 0, 0, 1], 4000));
+    // only the routes registered before route_layer are signed
+    let signed = middleware::from_fn_with_state(state.clone(), auth::verify);
 
-    Server::bind(&addr)
+    let app = Router::new()
+        .route(
+            "/",
+            // uploads are limited by max_size while streaming
+            post(add)
+                .route_layer(DefaultBodyLimit::disable())
+                .get(list)
+                .route_layer(signed.clone()),
+        )
+        .route(
+            "/:id",
+            delete(remove)
+                .patch(visibility)
+                .route_layer(signed.clone())
+                .get(image),
+        )
+        .route(
+            "/thumb/:id",
+            post(regenerate).route_layer(signed).get(thumb),
+        )
+        .route("/thumb/:preset/:id", get(preset_thumb))
+        .route("/resize/:id", get(resize))
+        .route("/meta/:id", get(meta))
+        .with_state(state);
+
+    Server::bind(&config.bind)
         .serve(app.into_make_service())
         .await
         .map_err(|_| Error::Server)?;
@@ -0,0 +1,172 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Seek},
+    path::Path,
+    process::Command,
+};
+
+use image::{
+    codecs::{gif::GifDecoder, jpeg::JpegDecoder, png::PngDecoder, webp::WebPDecoder},
+    io::Reader,
+    AnimationDecoder, Frames, ImageDecoder, ImageFormat, ImageResult,
+};
+use serde::{Deserialize, Serialize};
+
+// videos are read with it, without it they only get their size
+const FFPROBE: &str = "ffprobe";
+
+// returned to the backend, which keeps it on the image record
+#[derive(Debug, Serialize, Default)]
+pub struct Media {
+    pub size: u64,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    // animated images and videos, duration in milliseconds
+    pub frames: Option<u32>,
+    pub duration: Option<u64>,
+    // pixel layout of the decoded image, rgb8, rgba16...
+    pub color: Option<String>,
+    pub icc_profile: bool,
+}
+
+// images are read by their decoders and videos by ffprobe,
+// anything else only gets its size
+pub fn extract(path: &Path, content_type: &str, size: u64) -> Media {
+    let mut media = Media {
+        size,
+        ..Default::default()
+    };
+
+    let read = match ImageFormat::from_mime_type(content_type) {
+        Some(format) => File::open(path)
+            .map_err(|_| ())
+            .and_then(|f| read(BufReader::new(f), format, &mut media).map_err(|_| ())),
+        None if content_type.starts_with("video/") => probe(path, &mut media),
+        None => Ok(()),
+    };
+
+    if read.is_err() {
+        println!("Media: cannot read a {} file", content_type);
+    }
+
+    media
+}
+
+fn read<R: BufRead + Seek + 'static>(
+    reader: R,
+    format: ImageFormat,
+    media: &mut Media,
+) -> ImageResult<()> {
+    match format {
+        ImageFormat::Png => {
+            let mut decoder = PngDecoder::new(reader)?;
+            describe(&mut decoder, media);
+
+            if decoder.is_apng() {
+                animation(decoder.apng().into_frames(), media)?;
+            }
+        }
+        ImageFormat::Gif => {
+            let mut decoder = GifDecoder::new(reader)?;
+            describe(&mut decoder, media);
+            animation(decoder.into_frames(), media)?;
+        }
+        ImageFormat::WebP => {
+            let mut decoder = WebPDecoder::new(reader)?;
+            describe(&mut decoder, media);
+
+            if decoder.has_animation() {
+                animation(decoder.into_frames(), media)?;
+            }
+        }
+        ImageFormat::Jpeg => describe(&mut JpegDecoder::new(reader)?, media),
+        _ => {
+            let (width, height) = Reader::with_format(reader, format).into_dimensions()?;
+            media.width = Some(width);
+            media.height = Some(height);
+        }
+    }
+
+    Ok(())
+}
+
+fn describe<'a, D: ImageDecoder<'a>>(decoder: &mut D, media: &mut Media) {
+    let (width, height) = decoder.dimensions();
+
+    media.width = Some(width);
+    media.height = Some(height);
+    media.color = Some(format!("{:?}", decoder.color_type()).to_lowercase());
+    media.icc_profile = decoder.icc_profile().is_some();
+}
+
+// every frame is decoded to read its delay
+fn animation(frames: Frames, media: &mut Media) -> ImageResult<()> {
+    let mut count = 0;
+    let mut duration = 0;
+
+    for frame in frames {
+        let (numerator, denominator) = frame?.delay().numer_denom_ms();
+
+        count += 1;
+        duration += (numerator / denominator.max(1)) as u64;
+    }
+
+    media.frames = Some(count);
+    media.duration = Some(duration);
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct Probe {
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+    format: Option<ProbeFormat>,
+}
+
+// ffprobe writes numbers it does not always know as strings
+#[derive(Deserialize)]
+struct ProbeStream {
+    width: Option<u32>,
+    height: Option<u32>,
+    nb_frames: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ProbeFormat {
+    duration: Option<String>,
+}
+
+// the first video stream, the duration is the one of the container
+fn probe(path: &Path, media: &mut Media) -> Result<(), ()> {
+    let output = Command::new(FFPROBE)
+        .args(["-v", "error", "-select_streams", "v:0"])
+        .args([
+            "-show_entries",
+            "stream=width,height,nb_frames:format=duration",
+        ])
+        .args(["-of", "json"])
+        .arg(path)
+        .output()
+        .map_err(|_| ())?;
+
+    if !output.status.success() {
+        return Err(());
+    }
+
+    let probe: Probe = serde_json::from_slice(&output.stdout).map_err(|_| ())?;
+
+    if let Some(stream) = probe.streams.first() {
+        media.width = stream.width;
+        media.height = stream.height;
+        media.frames = stream.nb_frames.as_deref().and_then(|f| f.parse().ok());
+    }
+
+    media.duration = probe
+        .format
+        .and_then(|f| f.duration)
+        .and_then(|d| d.parse::<f64>().ok())
+        .map(|d| (d * 1000.0) as u64);
+
+    Ok(())
+}
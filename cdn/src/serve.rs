@@ -0,0 +1,198 @@
+use std::ops::Range;
+
+use axum::{
+    body::StreamBody,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+
+use crate::{
+    storage::{Kind, Storage},
+    Error,
+};
+
+// assets are addressed by their hash, so they never change
+const CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+enum Requested {
+    Full,
+    Partial(Range<u64>),
+    Unsatisfiable,
+}
+
+// only single ranges are supported, anything else is served in full
+fn parse_range(value: &str, size: u64) -> Requested {
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return Requested::Full;
+    };
+
+    if spec.contains(',') {
+        return Requested::Full;
+    }
+
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return Requested::Full;
+    };
+
+    let range = match (start.parse::<u64>(), end.parse::<u64>()) {
+        (Ok(start), Ok(end)) if start <= end => start..end.saturating_add(1).min(size),
+        (Ok(start), Err(_)) if end.is_empty() => start..size,
+        (Err(_), Ok(suffix)) if start.is_empty() => size.saturating_sub(suffix)..size,
+        _ => return Requested::Full,
+    };
+
+    if range.start >= size || range.is_empty() {
+        return Requested::Unsatisfiable;
+    }
+
+    Requested::Partial(range)
+}
+
+fn matches(value: &HeaderValue, etag: &str) -> bool {
+    let Ok(value) = value.to_str() else {
+        return false;
+    };
+
+    value
+        .split(',')
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == "*" || tag == etag)
+}
+
+pub async fn serve(
+    storage: &dyn Storage,
+    kind: Kind<'_>,
+    hash: &str,
+    headers: &HeaderMap,
+) -> Result<Response, Error> {
+    // thumbnails of the same hash differ by size and format
+    let etag = match kind {
+        Kind::Asset => format!("\"{}\"", hash),
+        Kind::Thumb(variant) => format!("\"{}-{}\"", hash, variant),
+        Kind::LegacyThumb => format!("\"{}-legacy\"", hash),
+        Kind::Private => return Err(Error::NotFound),
+    };
+
+    let mut response = HeaderMap::new();
+    response.insert(header::ETAG, etag.parse().map_err(|_| Error::Read)?);
+    response.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static(CACHE_CONTROL),
+    );
+    response.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+
+    if let Some(value) = headers.get(header::IF_NONE_MATCH) {
+        if matches(value, &etag) {
+            return Ok((StatusCode::NOT_MODIFIED, response).into_response());
+        }
+    }
+
+    let meta = storage.meta(kind, hash).await?;
+
+    let content_type = HeaderValue::from_str(&meta.content_type)
+        .unwrap_or(HeaderValue::from_static("application/octet-stream"));
+    response.insert(header::CONTENT_TYPE, content_type);
+
+    // a range for another version of the file is ignored
+    let current = match headers.get(header::IF_RANGE) {
+        Some(value) => value.to_str().map_or(false, |v| v == etag),
+        None => true,
+    };
+
+    let requested = match headers.get(header::RANGE).and_then(|r| r.to_str().ok()) {
+        Some(value) if current => parse_range(value, meta.size),
+        _ => Requested::Full,
+    };
+
+    let (status, range) = match requested {
+        Requested::Full => (StatusCode::OK, 0..meta.size),
+        Requested::Partial(range) => {
+            let content_range = format!("bytes {}-{}/{}", range.start, range.end - 1, meta.size);
+            response.insert(
+                header::CONTENT_RANGE,
+                content_range.parse().map_err(|_| Error::Read)?,
+            );
+
+            (StatusCode::PARTIAL_CONTENT, range)
+        }
+        Requested::Unsatisfiable => {
+            let content_range = format!("bytes */{}", meta.size);
+            response.insert(
+                header::CONTENT_RANGE,
+                content_range.parse().map_err(|_| Error::Read)?,
+            );
+
+            return Ok((StatusCode::RANGE_NOT_SATISFIABLE, response).into_response());
+        }
+    };
+
+    response.insert(header::CONTENT_LENGTH, (range.end - range.start).into());
+
+    let body = StreamBody::new(storage.stream(kind, hash, range).await?);
+
+    Ok((status, response, body).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: u64 = 1000;
+
+    fn partial(value: &str) -> Range<u64> {
+        match parse_range(value, SIZE) {
+            Requested::Partial(range) => range,
+            _ => panic!("{} is not a partial range", value),
+        }
+    }
+
+    #[test]
+    fn single_ranges() {
+        assert_eq!(partial("bytes=0-499"), 0..500);
+        assert_eq!(partial("bytes=500-"), 500..SIZE);
+        assert_eq!(partial(" bytes=10-10 "), 10..11);
+        // clamped to the end of the file
+        assert_eq!(partial("bytes=900-2000"), 900..SIZE);
+        assert_eq!(partial("bytes=0-18446744073709551615"), 0..SIZE);
+    }
+
+    #[test]
+    fn suffix_ranges() {
+        assert_eq!(partial("bytes=-200"), 800..SIZE);
+        assert_eq!(partial("bytes=-2000"), 0..SIZE);
+        assert!(matches!(
+            parse_range("bytes=-0", SIZE),
+            Requested::Unsatisfiable
+        ));
+    }
+
+    #[test]
+    fn unsatisfiable() {
+        assert!(matches!(
+            parse_range("bytes=1000-", SIZE),
+            Requested::Unsatisfiable
+        ));
+        assert!(matches!(
+            parse_range("bytes=2000-3000", SIZE),
+            Requested::Unsatisfiable
+        ));
+    }
+
+    #[test]
+    fn served_in_full() {
+        for value in [
+            "bytes=0-1,5-9",
+            "bytes=-10, 20-30",
+            "items=0-1",
+            "bytes=5-1",
+            "bytes=abc",
+            "bytes=-",
+        ] {
+            assert!(
+                matches!(parse_range(value, SIZE), Requested::Full),
+                "{}",
+                value
+            );
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use axum::{async_trait, body::Bytes};
+use futures::stream::BoxStream;
+
+use crate::{
+    config::{Backend, Config},
+    Error,
+};
+
+use self::{fs::FsStorage, s3::S3Storage};
+
+pub mod fs;
+pub mod s3;
+
+pub type Store = Arc<dyn Storage>;
+pub type ByteStream = BoxStream<'static, io::Result<Bytes>>;
+
+// enough for infer to recognize every format it knows
+pub const SNIFF_SIZE: u64 = 8192;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Kind<'a> {
+    Asset,
+    // thumbnails are stored per preset or resize variant
+    Thumb(&'a str),
+    // empty marker, the files of the hash need a signed url
+    Private,
+    // single jpeg thumbnail of the first layout, directly under thumbs,
+    // served until the thumbnails are regenerated
+    LegacyThumb,
+}
+
+pub struct Meta {
+    pub size: u64,
+    pub content_type: String,
+}
+
+#[async_trait]
+pub trait Storage: Send + Sync {
+    async fn exists(&self, kind: Kind<'_>, hash: &str) -> Result<bool, Error>;
+
+    async fn meta(&self, kind: Kind<'_>, hash: &str) -> Result<Meta, Error>;
+
+    async fn read(&self, kind: Kind<'_>, hash: &str) -> Result<Vec<u8>, Error>;
+
+    async fn stream(
+        &self,
+        kind: Kind<'_>,
+        hash: &str,
+        range: Range<u64>,
+    ) -> Result<ByteStream, Error>;
+
+    async fn write(
+        &self,
+        kind: Kind<'_>,
+        hash: &str,
+        data: Vec<u8>,
+        content_type: &str,
+    ) -> Result<(), Error>;
+
+    // streamed from a local file, used for uploads
+    async fn write_file(
+        &self,
+        kind: Kind<'_>,
+        hash: &str,
+        path: &Path,
+        content_type: &str,
+    ) -> Result<(), Error>;
+
+    // deleting a missing file is not an error
+    async fn delete(&self, kind: Kind<'_>, hash: &str) -> Result<(), Error>;
+
+    // (hash, last modification in unix seconds)
+    async fn hashes(&self, kind: Kind<'_>) -> Result<Vec<(String, i64)>, Error>;
+
+    async fn variants(&self) -> Result<Vec<String>, Error>;
+}
+
+// md5, or sha256 for files stored under the stronger hash
+pub fn is_hash(hash: &str) -> bool {
+    matches!(hash.len(), 32 | 64) && hash.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+// removes the original and every thumbnail of a hash
+pub async fn purge(storage: &dyn Storage, hash: &str) -> Result<(), Error> {
+    for variant in storage.variants().await? {
+        storage.delete(Kind::Thumb(&variant), hash).await?;
+    }
+
+    storage.delete(Kind::LegacyThumb, hash).await?;
+    storage.delete(Kind::Asset, hash).await?;
+    storage.delete(Kind::Private, hash).await
+}
+
+// removes the thumbnails of the variants not in current,
+// only image files are deleted, anything else found there is left alone
+pub async fn prune(
+    storage: &dyn Storage,
+    hash: &str,
+    current: &BTreeSet<String>,
+) -> Result<(), Error> {
+    for variant in storage.variants().await? {
+        if current.contains(&variant) {
+            continue;
+        }
+
+        match storage.meta(Kind::Thumb(&variant), hash).await {
+            Ok(meta) if meta.content_type.starts_with("image/") => {
+                storage.delete(Kind::Thumb(&variant), hash).await?
+            }
+            Ok(_) | Err(Error::NotFound) => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+// every hash with an original or a thumbnail, with its latest modification
+pub async fn stored(storage: &dyn Storage) -> Result<BTreeMap<String, i64>, Error> {
+    let mut kinds = vec![Kind::Asset, Kind::Private, Kind::LegacyThumb];
+    let variants = storage.variants().await?;
+    kinds.extend(variants.iter().map(|v| Kind::Thumb(v)));
+
+    let mut hashes = BTreeMap::new();
+    for kind in kinds {
+        for (hash, modified) in storage.hashes(kind).await? {
+            let latest = hashes.entry(hash).or_insert(modified);
+            *latest = modified.max(*latest);
+        }
+    }
+
+    Ok(hashes)
+}
+
+// files stored without a type fall back to their magic bytes
+pub fn sniff(data: &[u8]) -> String {
+    match infer::get(data) {
+        Some(t) => t.mime_type().to_string(),
+        None => "application/octet-stream".to_string(),
+    }
+}
+
+pub fn from_config(config: &Config) -> Result<Store, Error> {
+    let store: Store = match config.storage {
+        Backend::Fs => {
+            let storage = FsStorage::new(&config.assets, &config.thumbs, &config.private);
+            storage.move_markers()?;
+
+            Arc::new(storage)
+        }
+        Backend::S3 => Arc::new(S3Storage::new(&config.s3)?),
+    };
+
+    Ok(store)
+}
+
+// ab/cd/ef/rest, so no directory holds too many files
+fn split(hash: &str) -> Vec<&str> {
+    let (x, rest) = hash.split_at(2);
+    let (y, rest) = rest.split_at(2);
+    let (z, rest) = rest.split_at(2);
+
+    vec![x, y, z, rest]
+}
+
+// directories that can sit next to the variants and are never thumbnails
+const RESERVED: [&str; 2] = ["private", "cache"];
+
+// named like the presets and resizes, the ab/ directories of the legacy thumbnails
+// and anything else under thumbs are not variants
+fn is_variant(name: &str) -> bool {
+    name.len() > 2
+        && !RESERVED.contains(&name)
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+}
+
+pub fn relative_path(hash: &str) -> PathBuf {
+    split(hash).into_iter().collect()
+}
+
+pub fn key(prefix: &str, hash: &str) -> String {
+    let mut parts = vec![prefix];
+    parts.extend(split(hash));
+
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    const HASH: &str = "0123456789abcdef0123456789abcdef";
+
+    // the thumbnail cleanup of regenerate, with every kind of file next to the thumbnails
+    #[tokio::test]
+    async fn prune_keeps_private_files_private() {
+        let root = env::temp_dir().join(format!("cdn-prune-{}", process::id()));
+        let storage = FsStorage::new(
+            &root.join("assets"),
+            &root.join("thumbs"),
+            &root.join("private"),
+        );
+
+        let files: [(Kind, &[u8], &str); 5] = [
+            (Kind::Asset, b"original", "image/png"),
+            (Kind::Private, b"", "text/plain"),
+            (Kind::Thumb("small-jpeg"), b"current", "image/jpeg"),
+            (Kind::Thumb("small"), b"stale", "image/jpeg"),
+            (Kind::Thumb("notes"), b"not a thumbnail", "text/plain"),
+        ];
+        for (kind, data, content_type) in files {
+            storage
+                .write(kind, HASH, data.to_vec(), content_type)
+                .await
+                .unwrap();
+        }
+
+        let current = BTreeSet::from(["small-jpeg".to_string()]);
+        prune(&storage, HASH, &current).await.unwrap();
+
+        for kind in [
+            Kind::Private,
+            Kind::Asset,
+            Kind::Thumb("small-jpeg"),
+            Kind::Thumb("notes"),
+        ] {
+            assert!(storage.exists(kind, HASH).await.unwrap(), "{:?}", kind);
+        }
+        assert!(!storage.exists(Kind::Thumb("small"), HASH).await.unwrap());
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn variants() {
+        assert!(is_variant("small-jpeg"));
+        assert!(is_variant("64x64-contain-webp"));
+        assert!(!is_variant("ab"));
+        assert!(!is_variant("private"));
+        assert!(!is_variant("cache"));
+        assert!(!is_variant("Some Dir"));
+    }
+}
@@ -0,0 +1,292 @@
+use std::{
+    io::{ErrorKind, SeekFrom},
+    ops::Range,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use axum::async_trait;
+use futures::StreamExt;
+use tokio::{
+    fs::{self, File},
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+use tokio_util::io::ReaderStream;
+
+use crate::Error;
+
+use super::{
+    is_hash, is_variant, relative_path, sniff, ByteStream, Kind, Meta, Storage, SNIFF_SIZE,
+};
+
+pub struct FsStorage {
+    assets: PathBuf,
+    thumbs: PathBuf,
+    // outside of the other two, so it is never taken for a thumbnail variant
+    private: PathBuf,
+}
+
+impl FsStorage {
+    pub fn new(assets: &Path, thumbs: &Path, private: &Path) -> Self {
+        Self {
+            assets: assets.to_path_buf(),
+            thumbs: thumbs.to_path_buf(),
+            private: private.to_path_buf(),
+        }
+    }
+
+    // the markers used to be under assets/private, they are moved once at startup
+    pub fn move_markers(&self) -> Result<(), Error> {
+        let legacy = self.assets.join("private");
+
+        if legacy == self.private || !legacy.is_dir() {
+            return Ok(());
+        }
+
+        move_dir(&legacy, &self.private)?;
+        println!(
+            "Storage: private markers moved to {}",
+            self.private.display()
+        );
+
+        Ok(())
+    }
+
+    fn root(&self, kind: Kind<'_>) -> PathBuf {
+        match kind {
+            Kind::Asset => self.assets.clone(),
+            Kind::Thumb(variant) => self.thumbs.join(variant),
+            Kind::Private => self.private.clone(),
+            Kind::LegacyThumb => self.thumbs.clone(),
+        }
+    }
+
+    fn path(&self, kind: Kind<'_>, hash: &str) -> PathBuf {
+        self.root(kind).join(relative_path(hash))
+    }
+
+    // with thumbs and assets in the same directory, the legacy thumbnail is the original
+    fn is_original(&self, kind: Kind<'_>, hash: &str) -> bool {
+        matches!(kind, Kind::LegacyThumb) && self.path(kind, hash) == self.path(Kind::Asset, hash)
+    }
+
+    fn type_path(path: &Path) -> PathBuf {
+        path.with_extension("mime")
+    }
+}
+
+#[async_trait]
+impl Storage for FsStorage {
+    async fn exists(&self, kind: Kind<'_>, hash: &str) -> Result<bool, Error> {
+        if self.is_original(kind, hash) {
+            return Ok(false);
+        }
+
+        fs::try_exists(self.path(kind, hash))
+            .await
+            .map_err(|_| Error::Read)
+    }
+
+    async fn meta(&self, kind: Kind<'_>, hash: &str) -> Result<Meta, Error> {
+        let path = self.path(kind, hash);
+
+        if !self.exists(kind, hash).await? {
+            return Err(Error::NotFound);
+        }
+
+        let size = fs::metadata(&path).await.map_err(|_| Error::Read)?.len();
+
+        let content_type = match fs::read_to_string(Self::type_path(&path)).await {
+            Ok(t) => t,
+            Err(_) => {
+                let mut head = vec![];
+                File::open(&path)
+                    .await
+                    .map_err(|_| Error::Read)?
+                    .take(SNIFF_SIZE)
+                    .read_to_end(&mut head)
+                    .await
+                    .map_err(|_| Error::Read)?;
+
+                sniff(&head)
+            }
+        };
+
+        Ok(Meta { size, content_type })
+    }
+
+    async fn read(&self, kind: Kind<'_>, hash: &str) -> Result<Vec<u8>, Error> {
+        if !self.exists(kind, hash).await? {
+            return Err(Error::NotFound);
+        }
+
+        fs::read(self.path(kind, hash))
+            .await
+            .map_err(|_| Error::Read)
+    }
+
+    async fn stream(
+        &self,
+        kind: Kind<'_>,
+        hash: &str,
+        range: Range<u64>,
+    ) -> Result<ByteStream, Error> {
+        if self.is_original(kind, hash) {
+            return Err(Error::NotFound);
+        }
+
+        let mut file = File::open(self.path(kind, hash))
+            .await
+            .map_err(|_| Error::NotFound)?;
+
+        file.seek(SeekFrom::Start(range.start))
+            .await
+            .map_err(|_| Error::Read)?;
+
+        let reader = file.take(range.end - range.start);
+
+        Ok(ReaderStream::new(reader).boxed())
+    }
+
+    async fn write(
+        &self,
+        kind: Kind<'_>,
+        hash: &str,
+        data: Vec<u8>,
+        content_type: &str,
+    ) -> Result<(), Error> {
+        let path = self.path(kind, hash);
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).await.map_err(|_| Error::Write)?;
+        }
+
+        fs::write(Self::type_path(&path), content_type)
+            .await
+            .map_err(|_| Error::Write)?;
+
+        fs::write(path, data).await.map_err(|_| Error::Write)
+    }
+
+    async fn write_file(
+        &self,
+        kind: Kind<'_>,
+        hash: &str,
+        from: &Path,
+        content_type: &str,
+    ) -> Result<(), Error> {
+        let path = self.path(kind, hash);
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).await.map_err(|_| Error::Write)?;
+        }
+
+        fs::write(Self::type_path(&path), content_type)
+            .await
+            .map_err(|_| Error::Write)?;
+
+        // copied, the temporary directory can be on another device
+        fs::copy(from, path).await.map_err(|_| Error::Write)?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, kind: Kind<'_>, hash: &str) -> Result<(), Error> {
+        if self.is_original(kind, hash) {
+            return Ok(());
+        }
+
+        let path = self.path(kind, hash);
+
+        for file in [Self::type_path(&path), path] {
+            match fs::remove_file(file).await {
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(_) => return Err(Error::Write),
+            }
+        }
+
+        Ok(())
+    }
+
+    // walks the ab/cd/ef/ directories, anything else in the root is ignored
+    async fn hashes(&self, kind: Kind<'_>) -> Result<Vec<(String, i64)>, Error> {
+        let mut hashes = vec![];
+        let mut dirs = vec![(self.root(kind), String::new())];
+
+        while let Some((dir, prefix)) = dirs.pop() {
+            let mut entries = match fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(_) => return Err(Error::Read),
+            };
+
+            while let Some(entry) = entries.next_entry().await.map_err(|_| Error::Read)? {
+                let name = entry.file_name().to_string_lossy().to_string();
+                let file_type = entry.file_type().await.map_err(|_| Error::Read)?;
+
+                if file_type.is_dir() && name.len() == 2 && prefix.len() < 6 {
+                    dirs.push((entry.path(), format!("{}{}", prefix, name)));
+                } else if file_type.is_file() && prefix.len() == 6 {
+                    let hash = format!("{}{}", prefix, name);
+                    if is_hash(&hash) {
+                        let modified = entry
+                            .metadata()
+                            .await
+                            .and_then(|m| m.modified())
+                            .map_err(|_| Error::Read)?
+                            .duration_since(UNIX_EPOCH)
+                            .map_err(|_| Error::Read)?;
+
+                        hashes.push((hash, modified.as_secs() as i64));
+                    }
+                }
+            }
+        }
+
+        Ok(hashes)
+    }
+
+    async fn variants(&self) -> Result<Vec<String>, Error> {
+        let mut variants = vec![];
+
+        let mut entries = match fs::read_dir(&self.thumbs).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(variants),
+            Err(_) => return Err(Error::Read),
+        };
+
+        while let Some(entry) = entries.next_entry().await.map_err(|_| Error::Read)? {
+            let file_type = entry.file_type().await.map_err(|_| Error::Read)?;
+
+            let name = entry.file_name().to_string_lossy().to_string();
+            if file_type.is_dir() && is_variant(&name) {
+                variants.push(name);
+            }
+        }
+
+        Ok(variants)
+    }
+}
+
+// merges from into to, files already in to are kept
+fn move_dir(from: &Path, to: &Path) -> Result<(), Error> {
+    std::fs::create_dir_all(to).map_err(|_| Error::Write)?;
+
+    for entry in std::fs::read_dir(from).map_err(|_| Error::Read)? {
+        let entry = entry.map_err(|_| Error::Read)?;
+        let target = to.join(entry.file_name());
+
+        if entry.file_type().map_err(|_| Error::Read)?.is_dir() {
+            move_dir(&entry.path(), &target)?;
+        } else if !target.exists() {
+            // copied when rename fails, the directories can be on different devices
+            if std::fs::rename(entry.path(), &target).is_err() {
+                std::fs::copy(entry.path(), &target).map_err(|_| Error::Write)?;
+                std::fs::remove_file(entry.path()).map_err(|_| Error::Write)?;
+            }
+        }
+    }
+
+    std::fs::remove_dir_all(from).map_err(|_| Error::Write)
+}
@@ -0,0 +1,252 @@
+use std::{io, ops::Range, path::Path};
+
+use ::s3::{bucket::Bucket, creds::Credentials, error::S3Error, region::Region};
+use axum::{async_trait, body::Bytes};
+use chrono::DateTime;
+use futures::{stream, StreamExt};
+use tokio::fs::File;
+
+use crate::{config::S3Config, Error};
+
+use super::{is_hash, is_variant, key, sniff, ByteStream, Kind, Meta, Storage, SNIFF_SIZE};
+
+// a stream fetches its range by parts of this size, as it is read
+const CHUNK_SIZE: u64 = 4 * 1024 * 1024;
+
+pub struct S3Storage {
+    bucket: Bucket,
+}
+
+async fn fetch(bucket: &Bucket, key: &str, range: Range<u64>) -> Result<Vec<u8>, Error> {
+    if range.is_empty() {
+        return Ok(vec![]);
+    }
+
+    match bucket
+        .get_object_range(key, range.start, Some(range.end - 1))
+        .await
+    {
+        Ok(res) if res.status_code() == 200 || res.status_code() == 206 => Ok(res.bytes().to_vec()),
+        Err(S3Error::Http(404, _)) => Err(Error::NotFound),
+        _ => Err(Error::Read),
+    }
+}
+
+impl S3Storage {
+    pub fn new(config: &S3Config) -> Result<Self, Error> {
+        let region = Region::Custom {
+            region: config.region.clone(),
+            endpoint: config.endpoint.clone(),
+        };
+
+        let credentials = Credentials::new(
+            Some(&config.access_key),
+            Some(&config.secret_key),
+            None,
+            None,
+            None,
+        )
+        .map_err(|_| Error::Config)?;
+
+        let bucket = Bucket::new(&config.bucket, region, credentials).map_err(|_| Error::Config)?;
+
+        // MinIO and most self-hosted stores don't support virtual-hosted buckets
+        let bucket = match config.path_style {
+            true => bucket.with_path_style(),
+            false => bucket,
+        };
+
+        Ok(Self { bucket })
+    }
+
+    async fn range(&self, kind: Kind<'_>, hash: &str, range: Range<u64>) -> Result<Vec<u8>, Error> {
+        fetch(&self.bucket, &Self::key(kind, hash), range).await
+    }
+
+    fn prefix(kind: Kind<'_>) -> String {
+        match kind {
+            Kind::Asset => "assets".to_string(),
+            Kind::Thumb(variant) => format!("thumbs/{}", variant),
+            Kind::Private => "private".to_string(),
+            Kind::LegacyThumb => "thumbs".to_string(),
+        }
+    }
+
+    fn key(kind: Kind<'_>, hash: &str) -> String {
+        key(&Self::prefix(kind), hash)
+    }
+}
+
+#[async_trait]
+impl Storage for S3Storage {
+    async fn exists(&self, kind: Kind<'_>, hash: &str) -> Result<bool, Error> {
+        match self.bucket.head_object(Self::key(kind, hash)).await {
+            Ok((_, 200)) => Ok(true),
+            Ok((_, 404)) | Err(S3Error::Http(404, _)) => Ok(false),
+            _ => Err(Error::Read),
+        }
+    }
+
+    async fn read(&self, kind: Kind<'_>, hash: &str) -> Result<Vec<u8>, Error> {
+        match self.bucket.get_object(Self::key(kind, hash)).await {
+            Ok(res) if res.status_code() == 200 => Ok(res.bytes().to_vec()),
+            Ok(res) if res.status_code() == 404 => Err(Error::NotFound),
+            Err(S3Error::Http(404, _)) => Err(Error::NotFound),
+            _ => Err(Error::Read),
+        }
+    }
+
+    async fn meta(&self, kind: Kind<'_>, hash: &str) -> Result<Meta, Error> {
+        let head = match self.bucket.head_object(Self::key(kind, hash)).await {
+            Ok((head, 200)) => head,
+            Ok((_, 404)) | Err(S3Error::Http(404, _)) => return Err(Error::NotFound),
+            _ => return Err(Error::Read),
+        };
+
+        let size = head.content_length.ok_or(Error::Read)? as u64;
+
+        let content_type = match head.content_type {
+            Some(t) => t,
+            None => sniff(&self.range(kind, hash, 0..size.min(SNIFF_SIZE)).await?),
+        };
+
+        Ok(Meta { size, content_type })
+    }
+
+    // objects are only fetched for the requested range, one chunk at a time
+    async fn stream(
+        &self,
+        kind: Kind<'_>,
+        hash: &str,
+        range: Range<u64>,
+    ) -> Result<ByteStream, Error> {
+        let end = range.end;
+        let chunk = move |start: u64| start..end.min(start + CHUNK_SIZE);
+
+        // the first chunk is read now, so a missing file is an error and not an empty body
+        let first = chunk(range.start);
+        let data = self.range(kind, hash, first.clone()).await?;
+
+        let bucket = self.bucket.clone();
+        let key = Self::key(kind, hash);
+        let rest = stream::unfold(first.end, move |start| {
+            let bucket = bucket.clone();
+            let key = key.clone();
+
+            async move {
+                if start >= end {
+                    return None;
+                }
+
+                let next = chunk(start);
+                let data = fetch(&bucket, &key, next.clone())
+                    .await
+                    .map(Bytes::from)
+                    .map_err(|_| io::Error::new(io::ErrorKind::Other, "s3 read failed"));
+
+                // stops after an error
+                let start = if data.is_ok() { next.end } else { end };
+                Some((data, start))
+            }
+        });
+
+        Ok(stream::once(async move { Ok(Bytes::from(data)) })
+            .chain(rest)
+            .boxed())
+    }
+
+    async fn write(
+        &self,
+        kind: Kind<'_>,
+        hash: &str,
+        data: Vec<u8>,
+        content_type: &str,
+    ) -> Result<(), Error> {
+        let res = self
+            .bucket
+            .put_object_with_content_type(Self::key(kind, hash), &data, content_type)
+            .await
+            .map_err(|_| Error::Write)?;
+
+        match res.status_code() {
+            200 => Ok(()),
+            _ => Err(Error::Write),
+        }
+    }
+
+    async fn write_file(
+        &self,
+        kind: Kind<'_>,
+        hash: &str,
+        path: &Path,
+        content_type: &str,
+    ) -> Result<(), Error> {
+        let mut file = File::open(path).await.map_err(|_| Error::Read)?;
+
+        // sent as a multipart upload, the file is never fully in memory
+        let status = self
+            .bucket
+            .put_object_stream_with_content_type(&mut file, Self::key(kind, hash), content_type)
+            .await
+            .map_err(|_| Error::Write)?;
+
+        match status {
+            200 => Ok(()),
+            _ => Err(Error::Write),
+        }
+    }
+
+    async fn delete(&self, kind: Kind<'_>, hash: &str) -> Result<(), Error> {
+        match self.bucket.delete_object(Self::key(kind, hash)).await {
+            Ok(res) if res.status_code() == 204 || res.status_code() == 200 => Ok(()),
+            Ok(res) if res.status_code() == 404 => Ok(()),
+            Err(S3Error::Http(404, _)) => Ok(()),
+            _ => Err(Error::Write),
+        }
+    }
+
+    async fn hashes(&self, kind: Kind<'_>) -> Result<Vec<(String, i64)>, Error> {
+        let prefix = format!("{}/", Self::prefix(kind));
+
+        let pages = self
+            .bucket
+            .list(prefix.clone(), None)
+            .await
+            .map_err(|_| Error::Read)?;
+
+        let hashes = pages
+            .into_iter()
+            .flat_map(|page| page.contents)
+            .filter_map(|object| {
+                let hash = object.key.strip_prefix(&prefix)?.replace('/', "");
+
+                // an unreadable date is never old enough to be collected
+                let modified = DateTime::parse_from_rfc3339(&object.last_modified)
+                    .map_or(i64::MAX, |t| t.timestamp());
+
+                is_hash(&hash).then_some((hash, modified))
+            })
+            .collect();
+
+        Ok(hashes)
+    }
+
+    async fn variants(&self) -> Result<Vec<String>, Error> {
+        let pages = self
+            .bucket
+            .list("thumbs/".to_string(), Some("/".to_string()))
+            .await
+            .map_err(|_| Error::Read)?;
+
+        let variants = pages
+            .into_iter()
+            .flat_map(|page| page.common_prefixes.unwrap_or_default())
+            .filter_map(|p| {
+                let variant = p.prefix.strip_prefix("thumbs/")?.trim_end_matches('/');
+                is_variant(variant).then(|| variant.to_string())
+            })
+            .collect();
+
+        Ok(variants)
+    }
+}
@@ -0,0 +1,230 @@
+use std::{
+    fs::File,
+    io::{BufReader, Cursor},
+    path::Path,
+};
+
+use axum::http::HeaderMap;
+use image::{
+    codecs::{avif::AvifEncoder, webp::WebPEncoder},
+    imageops::FilterType,
+    ColorType, DynamicImage, ImageEncoder, ImageOutputFormat,
+};
+use serde::Deserialize;
+use thumbnailer::{create_thumbnails, ThumbnailSize};
+
+use crate::{config, Error};
+
+const QUALITY: u8 = 85;
+const AVIF_SPEED: u8 = 8;
+
+#[derive(Debug, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Fit {
+    // keep the ratio, fit inside the box
+    #[default]
+    Contain,
+    // keep the ratio, fill the box and crop the rest
+    Cover,
+    // stretch to the box
+    Fill,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Jpeg,
+    Png,
+    // lossless only, quality is ignored
+    Webp,
+    Avif,
+}
+
+impl Format {
+    pub fn mime(&self) -> &'static str {
+        match self {
+            Format::Jpeg => "image/jpeg",
+            Format::Png => "image/png",
+            Format::Webp => "image/webp",
+            Format::Avif => "image/avif",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Format::Jpeg => "jpeg",
+            Format::Png => "png",
+            Format::Webp => "webp",
+            Format::Avif => "avif",
+        }
+    }
+
+    fn encode(&self, image: &DynamicImage, quality: u8) -> Result<Vec<u8>, Error> {
+        let mut buf = Cursor::new(Vec::new());
+
+        let res = match self {
+            // jpeg has no alpha channel
+            Format::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8())
+                .write_to(&mut buf, ImageOutputFormat::Jpeg(quality)),
+            Format::Png => image.write_to(&mut buf, ImageOutputFormat::Png),
+            Format::Webp => {
+                let rgba = image.to_rgba8();
+                WebPEncoder::new_lossless(&mut buf).write_image(
+                    rgba.as_raw(),
+                    rgba.width(),
+                    rgba.height(),
+                    ColorType::Rgba8,
+                )
+            }
+            Format::Avif => {
+                let rgba = image.to_rgba8();
+                AvifEncoder::new_with_speed_quality(&mut buf, AVIF_SPEED, quality).write_image(
+                    rgba.as_raw(),
+                    rgba.width(),
+                    rgba.height(),
+                    ColorType::Rgba8,
+                )
+            }
+        };
+
+        res.map_err(|_| Error::Write)?;
+
+        Ok(buf.into_inner())
+    }
+}
+
+pub fn variant(preset: &str, format: Format) -> String {
+    format!("{}-{}", preset, format.name())
+}
+
+// the best of the formats the client accepts, the first one by default
+pub fn negotiate(formats: &[Format], headers: &HeaderMap) -> Format {
+    let accept = headers
+        .get(axum::http::header::ACCEPT)
+        .and_then(|a| a.to_str().ok())
+        .unwrap_or("");
+
+    // the most specific range decides, so image/avif;q=0 excludes avif even with */*
+    let quality = |format: &Format| -> f32 {
+        accept
+            .split(',')
+            .filter_map(|item| {
+                let mut params = item.split(';').map(str::trim);
+                let mime = params.next()?;
+                let q = params
+                    .find_map(|p| p.strip_prefix("q="))
+                    .and_then(|q| q.parse().ok())
+                    .unwrap_or(1.0);
+
+                let (kind, _) = format.mime().split_once('/')?;
+                let specificity = match mime {
+                    m if m == format.mime() => 2,
+                    m if m == format!("{}/*", kind) => 1,
+                    "*/*" => 0,
+                    _ => return None,
+                };
+
+                Some((specificity, q))
+            })
+            .max_by(|a, b| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)))
+            .map_or(0.0, |(_, q)| q)
+    };
+
+    formats
+        .iter()
+        .copied()
+        .filter(|f| quality(f) > 0.0)
+        // max_by keeps the last maximum, iterate backwards to favor the configured order
+        .rev()
+        .max_by(|a, b| quality(a).total_cmp(&quality(b)))
+        .unwrap_or(formats[0])
+}
+
+// every (preset, format) of the configuration, with its content type
+pub fn presets(
+    path: &Path,
+    content_type: mime::Mime,
+) -> Result<Vec<(String, &'static str, Vec<u8>)>, Error> {
+    let presets = &config::get().presets;
+    let sizes = presets
+        .values()
+        .map(|p| ThumbnailSize::Custom((p.width, p.height)));
+
+    let reader = BufReader::new(File::open(path).map_err(|_| Error::Read)?);
+    let thumbs = create_thumbnails(reader, content_type, sizes).map_err(|_| Error::Write)?;
+
+    let mut variants = vec![];
+    for ((name, preset), thumb) in presets.iter().zip(thumbs) {
+        // png keeps the alpha channel until the final encoding
+        let mut png = Cursor::new(Vec::new());
+        thumb.write_png(&mut png).map_err(|_| Error::Write)?;
+        let image = image::load_from_memory(&png.into_inner()).map_err(|_| Error::Write)?;
+
+        for format in &preset.formats {
+            let data = format.encode(&image, preset.quality)?;
+            variants.push((variant(name, *format), format.mime(), data));
+        }
+    }
+
+    Ok(variants)
+}
+
+// the encoded image with its content type
+pub fn resize(
+    path: &Path,
+    width: u32,
+    height: u32,
+    fit: Fit,
+    format: Format,
+) -> Result<(&'static str, Vec<u8>), Error> {
+    let image = image::io::Reader::open(path)
+        .and_then(|r| r.with_guessed_format())
+        .map_err(|_| Error::Read)?
+        .decode()
+        .map_err(|_| Error::WrongMime)?;
+
+    let resized = match fit {
+        Fit::Contain if image.width() <= width && image.height() <= height => image,
+        Fit::Contain => image.resize(width, height, FilterType::Lanczos3),
+        Fit::Cover => image.resize_to_fill(width, height, FilterType::Lanczos3),
+        Fit::Fill => image.resize_exact(width, height, FilterType::Lanczos3),
+    };
+
+    // jpeg would drop the alpha channel, transparent images stay png
+    let format = match format {
+        Format::Jpeg if resized.color().has_alpha() => Format::Png,
+        format => format,
+    };
+
+    Ok((format.mime(), format.encode(&resized, QUALITY)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn negotiated(accept: &str) -> Format {
+        let mut headers = HeaderMap::new();
+        headers.insert(axum::http::header::ACCEPT, accept.parse().unwrap());
+
+        negotiate(&[Format::Jpeg, Format::Webp, Format::Avif], &headers)
+    }
+
+    #[test]
+    fn configured_order() {
+        assert_eq!(negotiated(""), Format::Jpeg);
+        assert_eq!(negotiated("*/*"), Format::Jpeg);
+        assert_eq!(negotiated("image/avif,image/webp,*/*;q=0.5"), Format::Webp);
+        assert_eq!(negotiated("image/avif,*/*;q=0.8"), Format::Avif);
+    }
+
+    #[test]
+    fn exclusions() {
+        assert_eq!(negotiated("image/avif;q=0,image/*"), Format::Jpeg);
+        assert_eq!(
+            negotiated("image/jpeg;q=0,image/webp;q=0,*/*"),
+            Format::Avif
+        );
+        assert_eq!(negotiated("image/*;q=0,image/avif"), Format::Avif);
+    }
+}
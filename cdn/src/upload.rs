@@ -0,0 +1,107 @@
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use axum::extract::multipart::Field;
+use futures::StreamExt;
+use ring::digest::{Context, SHA256};
+use tokio::{fs::File, io::AsyncWriteExt};
+
+use crate::{
+    auth, config,
+    storage::{Kind, Storage, SNIFF_SIZE},
+    Error,
+};
+
+// the same file can be uploaded twice at once
+static NEXT: AtomicU64 = AtomicU64::new(0);
+
+// removed once dropped, whether the upload succeeded or not
+pub struct TempFile {
+    pub path: PathBuf,
+}
+
+impl TempFile {
+    pub fn new(hash: &str) -> Self {
+        Self {
+            path: config::get().temp_dir.join(format!(
+                "{}-{}",
+                hash,
+                NEXT.fetch_add(1, Ordering::Relaxed)
+            )),
+        }
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+pub struct Received {
+    pub file: TempFile,
+    // start of the file, enough to sniff its type
+    pub head: Vec<u8>,
+    pub size: u64,
+    pub sha256: String,
+}
+
+// streams a multipart field to disk
+pub async fn receive(mut field: Field<'_>, hash: &str) -> Result<Received, Error> {
+    let config = config::get();
+
+    let file = TempFile::new(hash);
+    let mut out = File::create(&file.path).await.map_err(|_| Error::Write)?;
+
+    let mut head = vec![];
+    let mut size = 0;
+    let mut sha256 = Context::new(&SHA256);
+
+    while let Some(chunk) = field.chunk().await.map_err(|_| Error::WrongField)? {
+        size += chunk.len() as u64;
+        if size > config.max_size {
+            return Err(Error::TooLarge);
+        }
+
+        let missing = (SNIFF_SIZE as usize).saturating_sub(head.len());
+        head.extend_from_slice(&chunk[..missing.min(chunk.len())]);
+
+        sha256.update(&chunk);
+        out.write_all(&chunk).await.map_err(|_| Error::Write)?;
+    }
+
+    out.flush().await.map_err(|_| Error::Write)?;
+
+    let sha256 = auth::hex(sha256.finish().as_ref());
+
+    Ok(Received {
+        file,
+        head,
+        size,
+        sha256,
+    })
+}
+
+// a stored file copied to disk, for the decoders that need to seek
+pub async fn download(
+    storage: &dyn Storage,
+    kind: Kind<'_>,
+    hash: &str,
+) -> Result<TempFile, Error> {
+    let size = storage.meta(kind, hash).await?.size;
+
+    let file = TempFile::new(hash);
+    let mut out = File::create(&file.path).await.map_err(|_| Error::Write)?;
+
+    let mut stream = storage.stream(kind, hash, 0..size).await?;
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|_| Error::Read)?;
+        out.write_all(&chunk).await.map_err(|_| Error::Write)?;
+    }
+
+    out.flush().await.map_err(|_| Error::Write)?;
+
+    Ok(file)
+}